@@ -0,0 +1,5 @@
+//! Depends on `parser` with `default-features = false`. CI builds this crate
+//! for a bare-metal target, so any `std` use outside the `std` feature fails.
+#![no_std]
+
+pub use parser::*;
@@ -1,4 +1,9 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(dead_code)]
 
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
 pub mod parser_fun;
+pub mod parser_owned;
 pub mod parser_struct;
@@ -1,40 +1,383 @@
+#[cfg(not(feature = "std"))]
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::convert::TryFrom;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
-struct Element {
+pub struct Element {
     name: String,
     attributes: Vec<(String, String)>,
-    children: Vec<Element>,
+    children: Vec<Node>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    fn builder(name: &str) -> ElementBuilder {
+        ElementBuilder {
+            element: Element {
+                name: String::from(name),
+                attributes: vec![],
+                children: vec![],
+            },
+        }
+    }
+
+    fn child_elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(el) => Some(el),
+            Node::Text(_) => None,
+        })
+    }
+
+    // Updates the value in place when the key is already present, so the
+    // attribute keeps its position; otherwise appends it.
+    fn set_attr(&mut self, key: &str, value: &str) {
+        match self.attributes.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = String::from(value),
+            None => self
+                .attributes
+                .push((String::from(key), String::from(value))),
+        }
+    }
+
+    fn remove_attr(&mut self, key: &str) -> Option<String> {
+        let index = self.attributes.iter().position(|(k, _)| k == key)?;
+        Some(self.attributes.remove(index).1)
+    }
+
+    fn node_count(&self) -> usize {
+        1 + self
+            .child_elements()
+            .map(Element::node_count)
+            .sum::<usize>()
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.child_elements().map(Element::depth).max().unwrap_or(0)
+    }
+
+    fn text_content(&self) -> String {
+        let mut text = String::new();
+        for child in &self.children {
+            match child {
+                Node::Element(el) => text.push_str(&el.text_content()),
+                Node::Text(t) => text.push_str(t),
+            }
+        }
+        text
+    }
+
+    fn walk<F: FnMut(&Element, usize)>(&self, f: &mut F) {
+        self.walk_at(0, f);
+    }
+
+    fn walk_at<F: FnMut(&Element, usize)>(&self, depth: usize, f: &mut F) {
+        f(self, depth);
+        for child in self.child_elements() {
+            child.walk_at(depth + 1, f);
+        }
+    }
+
+    fn walk_mut<F: FnMut(&mut Element, usize)>(&mut self, f: &mut F) {
+        self.walk_mut_at(0, f);
+    }
+
+    fn walk_mut_at<F: FnMut(&mut Element, usize)>(&mut self, depth: usize, f: &mut F) {
+        f(self, depth);
+        for child in &mut self.children {
+            if let Node::Element(child) = child {
+                child.walk_mut_at(depth + 1, f);
+            }
+        }
+    }
+
+    fn transform<F: Fn(Element) -> Element>(mut self, f: &F) -> Element {
+        self.children = self
+            .children
+            .into_iter()
+            .map(|child| match child {
+                Node::Element(el) => Node::Element(el.transform(f)),
+                text => text,
+            })
+            .collect();
+        f(self)
+    }
+
+    fn equals_unordered(&self, other: &Element) -> bool {
+        let mut attributes: Vec<_> = self.attributes.iter().collect();
+        let mut other_attributes: Vec<_> = other.attributes.iter().collect();
+        attributes.sort();
+        other_attributes.sort();
+        self.name == other.name
+            && attributes == other_attributes
+            && self.children.len() == other.children.len()
+            && self
+                .children
+                .iter()
+                .zip(&other.children)
+                .all(|pair| match pair {
+                    (Node::Element(child), Node::Element(other_child)) => {
+                        child.equals_unordered(other_child)
+                    }
+                    (child, other_child) => child == other_child,
+                })
+    }
+
+    // For config-style documents: each `<key>value</key>` child becomes an
+    // entry. Children that have elements of their own are skipped, and a
+    // repeated key keeps its last value.
+    #[cfg(feature = "std")]
+    fn to_map(&self) -> std::collections::HashMap<String, String> {
+        self.child_elements()
+            .filter(|child| child.child_elements().next().is_none())
+            .map(|child| (child.name.clone(), child.text_content()))
+            .collect()
+    }
+
+    fn prune<F: Fn(&Element) -> bool>(mut self, keep: &F) -> Option<Element> {
+        if !keep(&self) {
+            return None;
+        }
+        self.children = self
+            .children
+            .into_iter()
+            .filter_map(|child| match child {
+                Node::Element(el) => el.prune(keep).map(Node::Element),
+                text => Some(text),
+            })
+            .collect();
+        Some(self)
+    }
+}
+
+// Attributes are hashed in sorted order; elements that compare equal still
+// hash equally since equal attribute lists sort the same way.
+impl core::hash::Hash for Element {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        let mut attributes: Vec<_> = self.attributes.iter().collect();
+        attributes.sort();
+        attributes.hash(state);
+        self.children.hash(state);
+    }
+}
+
+#[cfg(feature = "std")]
+fn intern(elements: &[Element]) -> Vec<std::rc::Rc<Element>> {
+    let mut seen: std::collections::HashMap<&Element, std::rc::Rc<Element>> =
+        std::collections::HashMap::new();
+    elements
+        .iter()
+        .map(|element| {
+            seen.entry(element)
+                .or_insert_with(|| std::rc::Rc::new(element.clone()))
+                .clone()
+        })
+        .collect()
+}
+
+struct ElementBuilder {
+    element: Element,
+}
+
+impl ElementBuilder {
+    fn attr(mut self, key: &str, value: &str) -> Self {
+        self.element
+            .attributes
+            .push((String::from(key), String::from(value)));
+        self
+    }
+
+    fn child(mut self, child: Element) -> Self {
+        self.element.children.push(Node::Element(child));
+        self
+    }
+
+    fn text(mut self, text: &str) -> Self {
+        self.element.children.push(Node::Text(String::from(text)));
+        self
+    }
+
+    fn build(self) -> Element {
+        self.element
+    }
+}
+
+impl core::fmt::Display for Element {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(&to_xml(self))
+    }
+}
+
+impl core::str::FromStr for Element {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_str(s)
+    }
+}
+
+impl TryFrom<&str> for Element {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let _span = TraceSpan::enter("parse_str");
+        parse_document(s, &ParserConfig::default())
+    }
 }
 
-type ParseResult<'a, Output> = Result<(&'a str, Output), &'a str>;
+pub(crate) type ParseResult<'a, Output> = Result<(&'a str, Output), &'a str>;
 
-trait Parser<'a, Output> {
+pub(crate) trait Parser<'a, Output> {
     fn parse(&self, input: &'a str) -> ParseResult<'a, Output>;
+
+    fn parse_complete(&self, input: &'a str) -> Result<Output, &'a str> {
+        match self.parse(input) {
+            Ok(("", output)) => Ok(output),
+            Ok((rest, _)) => Err(rest),
+            Err(rest) => Err(rest),
+        }
+    }
 }
 
 impl<'a, F, Output> Parser<'a, Output> for F
 where
     F: Fn(&'a str) -> ParseResult<Output>,
 {
+    #[inline]
     fn parse(&self, input: &'a str) -> ParseResult<'a, Output> {
         self(input)
     }
 }
 
-fn match_letter(c: char, input: &str) -> ParseResult<()> {
+fn match_letter(c: char, input: &str) -> ParseResult<'_, ()> {
     match input.chars().next() {
         Some(letter) if letter == c => Ok((&input[letter.len_utf8()..], ())),
         _ => Err(input),
     }
 }
 
-fn match_literal<'a>(expected: &'a str) -> impl Parser<'a, &str> {
+#[inline]
+pub(crate) fn match_literal<'a>(expected: &'a str) -> impl Parser<'a, &'a str> {
     move |input: &'a str| match input.get(0..expected.len()) {
         Some(next) if next == expected => Ok((&input[expected.len()..], expected)),
         _ => Err(input),
     }
 }
 
-fn match_ident(input: &str) -> ParseResult<String> {
+// Succeeds without consuming anything when `parser` does not match here.
+fn not_followed_by<'a, P, A>(parser: P) -> impl Parser<'a, ()>
+where
+    P: Parser<'a, A>,
+{
+    move |input| match parser.parse(input) {
+        Ok(_) => Err(input),
+        Err(_) => Ok((input, ())),
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
+}
+
+// `word` as a whole word, so `if` does not match the start of `iffy`.
+fn match_keyword<'a>(word: &'a str) -> impl Parser<'a, &'a str> {
+    left(
+        match_literal(word),
+        not_followed_by(pred(any_char, |c| is_ident_char(*c))),
+    )
+}
+
+// A `match_literal` that is `Copy`, so one value can be shared by every rule
+// that needs it instead of building a parser per use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Literal(&'static str);
+
+impl<'a> Parser<'a, &'static str> for Literal {
+    fn parse(&self, input: &'a str) -> ParseResult<'a, &'static str> {
+        match input.strip_prefix(self.0) {
+            Some(rest) => Ok((rest, self.0)),
+            None => Err(input),
+        }
+    }
+}
+
+// Hands out one `Literal` per distinct string, so equal literals registered
+// from different places share the same `&'static str`.
+#[derive(Debug, Default)]
+struct Literals {
+    interned: Vec<&'static str>,
+}
+
+impl Literals {
+    fn get(&mut self, literal: &'static str) -> Literal {
+        match self.interned.iter().find(|&&seen| seen == literal) {
+            Some(&seen) => Literal(seen),
+            None => {
+                self.interned.push(literal);
+                Literal(literal)
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.interned.len()
+    }
+}
+
+// Options are tried in order and the first prefix match wins, so when one
+// literal is a prefix of another (`"<"` and `"<="`) list the longer one first.
+fn match_any_literal<'a>(options: &'a [&'a str]) -> impl Parser<'a, &'a str> {
+    move |input: &'a str| {
+        for expected in options {
+            if let Ok(result) = match_literal(expected).parse(input) {
+                return Ok(result);
+            }
+        }
+        Err(input)
+    }
+}
+
+type ByteParseResult<'a, Output> = Result<(&'a [u8], Output), &'a [u8]>;
+
+// `Parser` is tied to `&str` input, so the byte variant is a plain closure
+// over `&[u8]` that skips UTF-8 handling entirely.
+fn match_literal_bytes<'a>(
+    expected: &'a [u8],
+) -> impl Fn(&'a [u8]) -> ByteParseResult<'a, &'a [u8]> {
+    move |input| {
+        if input.starts_with(expected) {
+            Ok((&input[expected.len()..], expected))
+        } else {
+            Err(input)
+        }
+    }
+}
+
+// The next byte of a `&str`. Only ASCII bytes can be split off without
+// cutting a char in half, so the lead byte of a multibyte char fails.
+fn any_byte(input: &str) -> ParseResult<'_, u8> {
+    match input.as_bytes().first() {
+        Some(&b) if b.is_ascii() => Ok((&input[1..], b)),
+        _ => Err(input),
+    }
+}
+
+fn byte<'a>(expected: u8) -> impl Parser<'a, u8> {
+    pred(any_byte, move |b| *b == expected)
+}
+
+pub(crate) fn match_ident(input: &str) -> ParseResult<'_, String> {
     let mut matched = String::new();
     let mut chars = input.chars();
 
@@ -43,7 +386,7 @@ fn match_ident(input: &str) -> ParseResult<String> {
         _ => return Err(input),
     };
 
-    while let Some(next) = chars.next() {
+    for next in chars {
         if next.is_alphabetic() || next == '-' {
             matched.push(next);
         } else {
@@ -55,7 +398,48 @@ fn match_ident(input: &str) -> ParseResult<String> {
     Ok((&input[next_index..], matched))
 }
 
-fn pair<'a, P1, P2, R1, R2>(p1: P1, p2: P2) -> impl Parser<'a, (R1, R2)>
+// An identifier that is not one of the `reserved` words. A reserved word
+// fails at its start, so another rule can still try it.
+fn ident_excluding<'a>(reserved: &'a [&'a str]) -> impl Parser<'a, String> {
+    pred(match_ident, move |ident| {
+        !reserved.contains(&ident.as_str())
+    })
+}
+
+// Whitespace-separated identifiers, up to the first token that is not one.
+// Identifiers here may also contain digits and `_` after the first letter.
+fn collect_idents(input: &str) -> Vec<String> {
+    let ident = map(
+        pair(
+            satisfy(char::is_alphabetic),
+            zero_or_more(satisfy(is_ident_char)),
+        ),
+        |(first, rest)| core::iter::once(first).chain(rest).collect::<String>(),
+    );
+    // `sep_by` matches nothing rather than failing, so there is no error to
+    // handle.
+    sep_by(ident, space1())
+        .parse(input)
+        .map_or_else(|_| Vec::new(), |(_, idents)| idents)
+}
+
+// Same grammar as `match_ident`, but returns a slice of the input instead of
+// building a new `String`.
+fn match_ident_ref(input: &str) -> ParseResult<'_, &str> {
+    let mut chars = input.char_indices();
+
+    match chars.next() {
+        Some((_, next)) if next.is_alphabetic() => {}
+        _ => return Err(input),
+    };
+
+    let end = chars
+        .find(|&(_, next)| !(next.is_alphabetic() || next == '-'))
+        .map_or(input.len(), |(i, _)| i);
+    Ok((&input[end..], &input[..end]))
+}
+
+pub(crate) fn pair<'a, P1, P2, R1, R2>(p1: P1, p2: P2) -> impl Parser<'a, (R1, R2)>
 where
     P1: Parser<'a, R1>,
     P2: Parser<'a, R2>,
@@ -68,7 +452,7 @@ where
     }
 }
 
-fn map<'a, P, F, A, B>(parser: P, map_fn: F) -> impl Parser<'a, B>
+pub(crate) fn map<'a, P, F, A, B>(parser: P, map_fn: F) -> impl Parser<'a, B>
 where
     P: Parser<'a, A>,
     F: Fn(A) -> B,
@@ -116,71 +500,2629 @@ where
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+fn map2<'a, P1, P2, A, B, C, F>(p1: P1, p2: P2, f: F) -> impl Parser<'a, C>
+where
+    P1: Parser<'a, A>,
+    P2: Parser<'a, B>,
+    F: Fn(A, B) -> C,
+{
+    move |input| {
+        let (rest, a) = p1.parse(input)?;
+        let (rest, b) = p2.parse(rest)?;
+        Ok((rest, f(a, b)))
+    }
+}
 
-    #[test]
-    fn test_letter() {
-        let c = '\u{1F601}';
-        let phrase = "\u{1F601} smile";
-        assert_eq!(match_letter(c, phrase), Ok((" smile", ())));
+fn map3<'a, P1, P2, P3, A, B, C, D, F>(p1: P1, p2: P2, p3: P3, f: F) -> impl Parser<'a, D>
+where
+    P1: Parser<'a, A>,
+    P2: Parser<'a, B>,
+    P3: Parser<'a, C>,
+    F: Fn(A, B, C) -> D,
+{
+    move |input| {
+        let (rest, a) = p1.parse(input)?;
+        let (rest, b) = p2.parse(rest)?;
+        let (rest, c) = p3.parse(rest)?;
+        Ok((rest, f(a, b, c)))
     }
+}
 
-    #[test]
-    fn test_literal() {
-        let lit = "\u{1F601}";
-        let phrase = "\u{1F601} smile";
-        assert_eq!(match_literal(lit).parse(phrase), Ok((" smile", lit)));
+fn collect_string<'a, P>(parser: P) -> impl Parser<'a, String>
+where
+    P: Parser<'a, Vec<char>>,
+{
+    map(parser, |chars| chars.into_iter().collect())
+}
+
+// `item (sep item)*`, possibly empty. A separator not followed by an item is
+// left unconsumed.
+fn sep_by<'a, P, S, A, B>(item: P, separator: S) -> impl Parser<'a, Vec<A>>
+where
+    P: Parser<'a, A>,
+    S: Parser<'a, B>,
+{
+    fold_sep_by(item, separator, Vec::new, |mut result, value| {
+        result.push(value);
+        result
+    })
+}
+
+// `a, b, c` with an optional trailing separator. Two separators in a row
+// mean a missing element, which fails at the second one.
+fn sep_by_trailing<'a, P, S, A, B>(parser: P, separator: S) -> impl Parser<'a, Vec<A>>
+where
+    P: Parser<'a, A>,
+    S: Parser<'a, B>,
+{
+    move |mut input: &'a str| {
+        let mut result = Vec::new();
+        while let Ok((next_input, item)) = parser.parse(input) {
+            result.push(item);
+            input = next_input;
+            match separator.parse(input) {
+                Ok((next_input, _)) => input = next_input,
+                Err(_) => return Ok((input, result)),
+            }
+        }
+        if !result.is_empty() && separator.parse(input).is_ok() {
+            return Err(input);
+        }
+        Ok((input, result))
     }
+}
 
-    #[test]
-    fn test_ident() {
-        let phrase = "demo-id>";
-        assert_eq!(match_ident(phrase), Ok((">", String::from("demo-id"))));
+fn many0_count<'a, P, A>(parser: P) -> impl Parser<'a, usize>
+where
+    P: Parser<'a, A>,
+{
+    move |input| {
+        let mut count = 0;
+        let mut to_parse = input;
+        while let Ok((rest, _)) = parser.parse(to_parse) {
+            count += 1;
+            to_parse = rest;
+        }
+        Ok((to_parse, count))
     }
+}
 
-    #[test]
-    fn test_pair() {
-        let phrase = "<demo-id>";
-        let less_parser = match_literal("<");
-        let great_parser = match_literal(">");
-        let pair_parser = pair(pair(less_parser, match_ident), great_parser);
-        assert_eq!(
-            pair_parser.parse(phrase),
-            Ok(("", (("<", String::from("demo-id")), ">")))
-        );
+// Folds every match into an accumulator instead of collecting a `Vec`.
+fn fold_many<'a, P, A, S, I, F>(parser: P, init: I, f: F) -> impl Parser<'a, S>
+where
+    P: Parser<'a, A>,
+    I: Fn() -> S,
+    F: Fn(S, A) -> S,
+{
+    move |input| {
+        let mut acc = init();
+        let mut to_parse = input;
+        while let Ok((rest, value)) = parser.parse(to_parse) {
+            acc = f(acc, value);
+            to_parse = rest;
+        }
+        Ok((to_parse, acc))
     }
+}
 
-    #[test]
-    fn test_zero_or_more() {
-        #[derive(Debug, PartialEq, Eq)]
-        struct Ident {
-            val: String,
+// `item (sep item)*`, folded as it goes. A separator not followed by an item
+// is left unconsumed. Matches nothing, yielding `init`, when the first item
+// is absent.
+fn repeat_sep_fold<'a, P, S, A, B, C, F>(item: P, sep: S, init: B, f: F) -> impl Parser<'a, B>
+where
+    P: Parser<'a, A>,
+    S: Parser<'a, C>,
+    B: Clone,
+    F: Fn(B, A) -> B,
+{
+    fold_sep_by(item, sep, move || init.clone(), f)
+}
+
+// The loop behind `sep_by` and `repeat_sep_fold`. Like `fold_many`, it takes
+// `init` as a function so each parse starts from a fresh accumulator.
+fn fold_sep_by<'a, P, S, A, B, C, I, F>(item: P, sep: S, init: I, f: F) -> impl Parser<'a, B>
+where
+    P: Parser<'a, A>,
+    S: Parser<'a, C>,
+    I: Fn() -> B,
+    F: Fn(B, A) -> B,
+{
+    move |input| {
+        let mut acc = init();
+        let mut to_parse = input;
+        let mut next = item.parse(to_parse);
+        while let Ok((rest, value)) = next {
+            acc = f(acc, value);
+            to_parse = rest;
+            next = sep
+                .parse(to_parse)
+                .and_then(|(after_sep, _)| item.parse(after_sep));
+        }
+        Ok((to_parse, acc))
+    }
+}
+
+fn many1_count<'a, P, A>(parser: P) -> impl Parser<'a, usize>
+where
+    P: Parser<'a, A>,
+{
+    pred(many0_count(parser), |count| *count > 0)
+}
+
+fn repeat_until_eof<'a, P, A>(parser: P) -> impl Parser<'a, Vec<A>>
+where
+    P: Parser<'a, A>,
+{
+    move |input: &'a str| {
+        let mut result = Vec::new();
+        let mut to_parse = input;
+        while !to_parse.is_empty() {
+            match parser.parse(to_parse) {
+                Ok((rest, parsed)) if rest.len() < to_parse.len() => {
+                    result.push(parsed);
+                    to_parse = rest;
+                }
+                _ => return Err(to_parse),
+            }
+        }
+        Ok((to_parse, result))
+    }
+}
+
+// Word-at-a-time search for one byte: a whole `usize` of input is tested
+// for a match at once, and only the word that has one is scanned bytewise.
+fn memchr(needle: u8, haystack: &[u8]) -> Option<usize> {
+    const WORD: usize = core::mem::size_of::<usize>();
+    const LO: usize = usize::MAX / 255;
+    const HI: usize = LO << 7;
+    let repeated = LO * usize::from(needle);
+    let mut i = 0;
+    while i + WORD <= haystack.len() {
+        let mut chunk = [0; WORD];
+        chunk.copy_from_slice(&haystack[i..i + WORD]);
+        let x = usize::from_ne_bytes(chunk) ^ repeated;
+        if x.wrapping_sub(LO) & !x & HI != 0 {
+            break;
+        }
+        i += WORD;
+    }
+    haystack[i..]
+        .iter()
+        .position(|&b| b == needle)
+        .map(|found| i + found)
+}
+
+// An ASCII byte never occurs inside a multibyte char, so for an ASCII
+// delimiter every `memchr` hit is a char boundary and the offsets match
+// `str::find`. Other delimiters fall back to `str::find`.
+fn find_fast(haystack: &str, needle: &str) -> Option<usize> {
+    let first = match needle.as_bytes().first() {
+        Some(&first) if needle.is_ascii() => first,
+        _ => return haystack.find(needle),
+    };
+    let bytes = haystack.as_bytes();
+    let mut start = 0;
+    while let Some(found) = memchr(first, &bytes[start..]) {
+        let at = start + found;
+        if bytes[at..].starts_with(needle.as_bytes()) {
+            return Some(at);
+        }
+        start = at + 1;
+    }
+    None
+}
+
+fn take_until<'a>(delimiter: &'a str) -> impl Parser<'a, &'a str> {
+    move |input: &'a str| {
+        let found = if cfg!(feature = "fast-search") {
+            find_fast(input, delimiter)
+        } else {
+            input.find(delimiter)
         };
+        match found {
+            Some(end) => Ok((&input[end..], &input[..end])),
+            None => Err(input),
+        }
+    }
+}
 
-        let phrase = "<demo-id><kaspa><xxx>";
-        let less_parser = match_literal("<");
-        let great_parser = match_literal(">");
-        let ident_parser = map(match_ident, |id| Ident { val: id });
-        let pair_parser = pair(pair(less_parser, ident_parser), great_parser);
-        assert_eq!(
-            zero_or_more(pair_parser).parse(phrase),
-            Ok((
-                "",
-                vec![
-                    (
-                        (
-                            "<",
-                            Ident {
-                                val: String::from("demo-id")
-                            }
-                        ),
-                        ">"
-                    ),
-                    (
-                        (
-                            "<",
+// Input must start with `open`; consumes through the matching `close` and
+// returns what lies between, nested pairs included. When `open` and `close`
+// are the same char nothing can nest, so the next one closes.
+fn balanced<'a>(open: char, close: char) -> impl Parser<'a, &'a str> {
+    move |input: &'a str| {
+        let mut chars = input.char_indices();
+        match chars.next() {
+            Some((_, next)) if next == open => {}
+            _ => return Err(input),
+        }
+        let mut depth = 0;
+        for (index, next) in chars {
+            if next == close {
+                if depth == 0 {
+                    let rest = &input[index + close.len_utf8()..];
+                    return Ok((rest, &input[open.len_utf8()..index]));
+                }
+                depth -= 1;
+            } else if next == open {
+                depth += 1;
+            }
+        }
+        Err(input)
+    }
+}
+
+// For input that arrives in chunks, running out of input is not a failure:
+// the caller should retry once more has been appended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StreamError {
+    Incomplete,
+}
+
+type StreamingResult<'a, Output> = Result<(&'a str, Output), StreamError>;
+
+// `take_until` for streaming input: a missing delimiter may still arrive, so
+// it reports `Incomplete` where `take_until` fails. Like the byte parsers this
+// is a plain closure, since `Parser` has no room for `Incomplete`.
+fn take_until_streaming<'a>(
+    delimiter: &'a str,
+) -> impl Fn(&'a str) -> StreamingResult<'a, &'a str> {
+    move |input| match take_until(delimiter).parse(input) {
+        Ok(result) => Ok(result),
+        Err(_) => Err(StreamError::Incomplete),
+    }
+}
+
+#[inline]
+fn any_char(input: &str) -> ParseResult<'_, char> {
+    match input.chars().next() {
+        Some(next) => Ok((&input[next.len_utf8()..], next)),
+        _ => Err(input),
+    }
+}
+
+// One extended grapheme cluster, approximately: a base char plus any
+// combining marks, variation selectors, emoji modifiers and tag chars that
+// follow it, with ZWJ gluing on the next char, regional indicators pairing
+// into flags and `\r\n` kept together. This covers emoji and accented text
+// but only approximates UAX #29: spacing marks (e.g. Devanagari U+093F) and
+// Hangul L/V/T jamo sequences are split into separate clusters.
+#[cfg(feature = "approx-graphemes")]
+fn any_grapheme<'a>() -> impl Parser<'a, &'a str> {
+    move |input: &'a str| {
+        let mut chars = input.char_indices();
+        let (_, first) = chars.next().ok_or(input)?;
+        let mut end = first.len_utf8();
+        let mut prev = first;
+        let mut regional = usize::from(is_regional_indicator(first));
+        for (i, c) in chars {
+            let joins = match (prev, c) {
+                ('\r', '\n') => true,
+                ('\r', _) | ('\n', _) => false,
+                (_, '\u{200D}') | ('\u{200D}', _) => true,
+                _ if is_grapheme_extend(c) => true,
+                _ => is_regional_indicator(c) && regional % 2 == 1,
+            };
+            if !joins {
+                break;
+            }
+            regional += usize::from(is_regional_indicator(c));
+            end = i + c.len_utf8();
+            prev = c;
+        }
+        Ok((&input[end..], &input[..end]))
+    }
+}
+
+#[cfg(feature = "approx-graphemes")]
+fn is_grapheme_extend(c: char) -> bool {
+    matches!(c,
+        '\u{300}'..='\u{36F}'
+        | '\u{483}'..='\u{489}'
+        | '\u{591}'..='\u{5BD}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}'
+        | '\u{1F3FB}'..='\u{1F3FF}'
+        | '\u{E0020}'..='\u{E007F}'
+        | '\u{E0100}'..='\u{E01EF}')
+}
+
+#[cfg(feature = "approx-graphemes")]
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c, '\u{1F1E6}'..='\u{1F1FF}')
+}
+
+fn pred<'a, P, A, F>(parser: P, predicate: F) -> impl Parser<'a, A>
+where
+    P: Parser<'a, A>,
+    F: Fn(&A) -> bool,
+{
+    move |input| {
+        if let Ok((next_input, value)) = parser.parse(input) {
+            if predicate(&value) {
+                return Ok((next_input, value));
+            }
+        }
+        Err(input)
+    }
+}
+
+// Byte offset into `original` of the point where `remaining` bytes of it are
+// left unparsed. Parsers only hand back the rest of their input, so positions,
+// spans and error offsets are all worked out from its length here.
+fn offset_in(original: &str, remaining: usize) -> usize {
+    original.len() - remaining
+}
+
+fn position<'a>(original: &'a str) -> impl Parser<'a, usize> {
+    move |input: &'a str| Ok((input, offset_in(original, input.len())))
+}
+
+fn spanned<'a, P, A>(original: &'a str, parser: P) -> impl Parser<'a, (A, (usize, usize))>
+where
+    P: Parser<'a, A>,
+{
+    move |input: &'a str| {
+        let start = offset_in(original, input.len());
+        parser
+            .parse(input)
+            .map(|(rest, value)| (rest, (value, (start, offset_in(original, rest.len())))))
+    }
+}
+
+fn map_with_span<'a, P, A, B, F>(original: &'a str, parser: P, f: F) -> impl Parser<'a, B>
+where
+    P: Parser<'a, A>,
+    F: Fn(A, (usize, usize)) -> B,
+{
+    map(spanned(original, parser), move |(value, span)| {
+        f(value, span)
+    })
+}
+
+// Applies `parser` until it fails, stops making progress, or the input runs
+// out, pairing each value with its start and end byte offset.
+fn parse_tokens<'a, P, A>(parser: P, input: &'a str) -> Vec<(A, usize, usize)>
+where
+    P: Parser<'a, A>,
+{
+    let mut tokens = Vec::new();
+    let mut rest = input;
+    while !rest.is_empty() {
+        match parser.parse(rest) {
+            Ok((next, value)) if next.len() < rest.len() => {
+                tokens.push((
+                    value,
+                    offset_in(input, rest.len()),
+                    offset_in(input, next.len()),
+                ));
+                rest = next;
+            }
+            _ => break,
+        }
+    }
+    tokens
+}
+
+// How many non-overlapping matches of `parser` the input contains, trying
+// again one char further on after each failure. Empty matches do not count.
+fn count_matches<'a, P, A>(parser: P, input: &'a str) -> usize
+where
+    P: Parser<'a, A>,
+{
+    let mut count = 0;
+    let mut rest = input;
+    while !rest.is_empty() {
+        match parser.parse(rest) {
+            Ok((next, _)) if next.len() < rest.len() => {
+                count += 1;
+                rest = next;
+            }
+            _ => {
+                let mut chars = rest.chars();
+                chars.next();
+                rest = chars.as_str();
+            }
+        }
+    }
+    count
+}
+
+// One char, if `f` accepts it.
+fn satisfy<'a, F>(f: F) -> impl Parser<'a, char>
+where
+    F: Fn(char) -> bool,
+{
+    pred(any_char, move |c| f(*c))
+}
+
+// An inverted range (`lo > hi`) matches nothing.
+fn char_range<'a>(lo: char, hi: char) -> impl Parser<'a, char> {
+    satisfy(move |c| lo <= c && c <= hi)
+}
+
+// Leading spaces at the current position, which callers keep at the start
+// of a line. Nothing is consumed.
+fn indentation<'a>() -> impl Parser<'a, usize> {
+    move |input: &'a str| Ok((input, input.len() - input.trim_start_matches(' ').len()))
+}
+
+fn indented_by<'a, P, A>(level: usize, parser: P) -> impl Parser<'a, A>
+where
+    P: Parser<'a, A>,
+{
+    move |input: &'a str| match indentation().parse(input) {
+        Ok((_, indent)) if indent == level => parser.parse(&input[indent..]).map_err(|_| input),
+        _ => Err(input),
+    }
+}
+
+// Open indentation levels for offside-rule formats, innermost last. The
+// outermost level is column 0 and is never popped.
+#[derive(Debug)]
+struct IndentStack {
+    levels: core::cell::RefCell<Vec<usize>>,
+}
+
+impl IndentStack {
+    fn new() -> Self {
+        IndentStack {
+            levels: core::cell::RefCell::new(vec![0]),
+        }
+    }
+
+    fn current(&self) -> usize {
+        *self.levels.borrow().last().unwrap_or(&0)
+    }
+}
+
+// Matches, without consuming, a line indented deeper than the current level
+// and pushes its indentation: the INDENT token.
+fn indent<'a, 's>(stack: &'s IndentStack) -> impl Parser<'a, ()> + 's {
+    move |input: &'a str| {
+        let (_, level) = indentation().parse(input)?;
+        if level > stack.current() {
+            stack.levels.borrow_mut().push(level);
+            Ok((input, ()))
+        } else {
+            Err(input)
+        }
+    }
+}
+
+// Matches, without consuming, a line indented less than the current level,
+// popping every level it closes and returning how many: one DEDENT each. The
+// line must line up with an enclosing level. Empty input closes all levels.
+fn dedent<'a, 's>(stack: &'s IndentStack) -> impl Parser<'a, usize> + 's {
+    move |input: &'a str| {
+        let (_, level) = indentation().parse(input)?;
+        let mut levels = stack.levels.borrow_mut();
+        let open = levels.iter().take_while(|&&open| open <= level).count();
+        if open == levels.len() || levels[open - 1] != level {
+            return Err(input);
+        }
+        let closed = levels.len() - open;
+        levels.truncate(open);
+        Ok((input, closed))
+    }
+}
+
+// A line at exactly the current level, with its indentation consumed.
+fn samedent<'a, 's, P, A>(stack: &'s IndentStack, parser: P) -> impl Parser<'a, A> + 's
+where
+    P: Parser<'a, A> + 's,
+{
+    move |input: &'a str| match indentation().parse(input) {
+        Ok((_, level)) if level == stack.current() => {
+            parser.parse(&input[level..]).map_err(|_| input)
+        }
+        _ => Err(input),
+    }
+}
+
+// Everything up to the next `\n`, which is consumed but not returned. The
+// last line may lack the newline; empty input has no line at all.
+fn line<'a>() -> impl Parser<'a, &'a str> {
+    move |input: &'a str| match input.find('\n') {
+        Some(end) => Ok((&input[end + 1..], &input[..end])),
+        None if input.is_empty() => Err(input),
+        None => Ok((&input[input.len()..], input)),
+    }
+}
+
+// Splits the rest of the input into lines and parses each one completely.
+fn lines<'a, P, A>(parser: P) -> impl Parser<'a, Vec<A>>
+where
+    P: Parser<'a, A>,
+{
+    move |mut input: &'a str| {
+        let mut result = Vec::new();
+        while let Ok((next_input, line)) = line().parse(input) {
+            result.push(parser.parse_complete(line)?);
+            input = next_input;
+        }
+        Ok((input, result))
+    }
+}
+
+fn scan<'a, S, A, F>(initial: S, step: F) -> impl Parser<'a, (S, Vec<A>)>
+where
+    S: Clone,
+    F: Fn(&mut S, char) -> Option<A>,
+{
+    move |input: &'a str| {
+        let mut state = initial.clone();
+        let mut result = Vec::new();
+        let mut consumed = 0;
+        for c in input.chars() {
+            match step(&mut state, c) {
+                Some(output) => result.push(output),
+                None => break,
+            }
+            consumed += c.len_utf8();
+        }
+        Ok((&input[consumed..], (state, result)))
+    }
+}
+
+struct BoxedParser<'a, Output> {
+    parser: Box<dyn Parser<'a, Output> + 'a>,
+    site: &'static core::panic::Location<'static>,
+}
+
+impl<'a, Output> BoxedParser<'a, Output> {
+    #[track_caller]
+    fn new<P>(parser: P) -> Self
+    where
+        P: Parser<'a, Output> + 'a,
+    {
+        BoxedParser {
+            parser: Box::new(parser),
+            site: core::panic::Location::caller(),
+        }
+    }
+}
+
+impl<'a, Output> Parser<'a, Output> for BoxedParser<'a, Output> {
+    fn parse(&self, input: &'a str) -> ParseResult<'a, Output> {
+        #[cfg(all(feature = "std", debug_assertions))]
+        let _guard = RecursionGuard::enter(self, input);
+        self.parser.parse(input)
+    }
+}
+
+// Each boxed parser instance is a grammar rule. If a rule is entered again
+// before the outer call consumed any input, it is left recursive and would
+// loop until the stack overflows. Rules are keyed by address, so two rules
+// built by the same helper are told apart; a parser rebuilt on every call is
+// a new rule each time and has to be shared, e.g. through a `Grammar`, to be
+// caught.
+#[cfg(all(feature = "std", debug_assertions))]
+std::thread_local! {
+    static ACTIVE_RULES: core::cell::RefCell<Vec<(usize, usize)>> =
+        const { core::cell::RefCell::new(Vec::new()) };
+}
+
+#[cfg(all(feature = "std", debug_assertions))]
+struct RecursionGuard;
+
+#[cfg(all(feature = "std", debug_assertions))]
+impl RecursionGuard {
+    fn enter<Output>(parser: &BoxedParser<'_, Output>, input: &str) -> Self {
+        let rule = (parser as *const _ as usize, input.as_ptr() as usize);
+        let reentered = ACTIVE_RULES.with(|active| {
+            let mut active = active.borrow_mut();
+            let reentered = active.contains(&rule);
+            active.push(rule);
+            reentered
+        });
+        if reentered {
+            panic!(
+                "left recursion detected: parser built at {} re-entered at the same input position without consuming input",
+                parser.site
+            );
+        }
+        RecursionGuard
+    }
+}
+
+#[cfg(all(feature = "std", debug_assertions))]
+impl Drop for RecursionGuard {
+    fn drop(&mut self) {
+        ACTIVE_RULES.with(|active| active.borrow_mut().pop());
+    }
+}
+
+#[cfg(feature = "std")]
+type RuleTable<'a, Output> = core::cell::RefCell<
+    std::collections::HashMap<String, std::rc::Rc<dyn Parser<'a, Output> + 'a>>,
+>;
+
+// Named rules defined at runtime. `rule` looks its rule up each time it
+// parses, so rules may refer to each other, or themselves, before they are
+// defined. Rule references hold the table weakly to avoid `Rc` cycles.
+#[cfg(feature = "std")]
+struct Grammar<'a, Output> {
+    rules: std::rc::Rc<RuleTable<'a, Output>>,
+    // Every name passed to `rule`, for `validate`.
+    referenced: core::cell::RefCell<Vec<String>>,
+}
+
+#[cfg(feature = "std")]
+impl<'a, Output: 'a> Grammar<'a, Output> {
+    fn new() -> Self {
+        Grammar {
+            rules: std::rc::Rc::new(core::cell::RefCell::new(std::collections::HashMap::new())),
+            referenced: core::cell::RefCell::new(Vec::new()),
+        }
+    }
+
+    fn define<P>(&self, name: &str, parser: P)
+    where
+        P: Parser<'a, Output> + 'a,
+    {
+        let rule: std::rc::Rc<dyn Parser<'a, Output> + 'a> =
+            std::rc::Rc::new(BoxedParser::new(parser));
+        self.rules.borrow_mut().insert(name.to_string(), rule);
+    }
+
+    // Fails to parse if `name` was never defined or the grammar has been
+    // dropped; `validate` reports undefined names up front.
+    fn rule(&self, name: &str) -> impl Parser<'a, Output> {
+        let rules = std::rc::Rc::downgrade(&self.rules);
+        let name = name.to_string();
+        self.referenced.borrow_mut().push(name.clone());
+        move |input: &'a str| {
+            let rule = rules
+                .upgrade()
+                .and_then(|rules| rules.borrow().get(&name).cloned())
+                .ok_or(input)?;
+            rule.parse(input)
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let rules = self.rules.borrow();
+        match self
+            .referenced
+            .borrow()
+            .iter()
+            .find(|name| !rules.contains_key(*name))
+        {
+            Some(name) => Err(format!("undefined grammar rule: {}", name)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn left<'a, P1, P2, R1, R2>(p1: P1, p2: P2) -> impl Parser<'a, R1>
+where
+    P1: Parser<'a, R1>,
+    P2: Parser<'a, R2>,
+{
+    map(pair(p1, p2), |(left, _right)| left)
+}
+
+fn right<'a, P1, P2, R1, R2>(p1: P1, p2: P2) -> impl Parser<'a, R2>
+where
+    P1: Parser<'a, R1>,
+    P2: Parser<'a, R2>,
+{
+    map(pair(p1, p2), |(_left, right)| right)
+}
+
+fn either<'a, P1, P2, A>(p1: P1, p2: P2) -> impl Parser<'a, A>
+where
+    P1: Parser<'a, A>,
+    P2: Parser<'a, A>,
+{
+    move |input| match p1.parse(input) {
+        ok @ Ok(_) => ok,
+        Err(_) => p2.parse(input),
+    }
+}
+
+// Unlike `either`, every alternative is run on each call and the successful
+// one with the largest priority wins, ties going to the earlier one. That is
+// one parse per alternative even when the first would have done, so prefer
+// ordering alternatives when that is enough.
+fn choice_by<'a, A>(parsers: Vec<(i32, BoxedParser<'a, A>)>) -> impl Parser<'a, A> {
+    move |input: &'a str| {
+        let mut best: Option<(i32, &'a str, A)> = None;
+        let mut furthest = input;
+        for (priority, parser) in &parsers {
+            match parser.parse(input) {
+                Ok((rest, output)) => {
+                    if best.as_ref().is_none_or(|(top, _, _)| priority > top) {
+                        best = Some((*priority, rest, output));
+                    }
+                }
+                Err(rest) if rest.len() < furthest.len() => furthest = rest,
+                Err(_) => {}
+            }
+        }
+        match best {
+            Some((_, rest, output)) => Ok((rest, output)),
+            None => Err(furthest),
+        }
+    }
+}
+
+// Like `choice` over the given parsers, but also returns the tag of the
+// branch that matched so callers can tell the alternatives apart.
+fn tagged_choice<'a, A>(
+    parsers: Vec<(&'a str, BoxedParser<'a, A>)>,
+) -> impl Parser<'a, (&'a str, A)> {
+    move |input: &'a str| {
+        let mut furthest = input;
+        for (tag, parser) in &parsers {
+            match parser.parse(input) {
+                Ok((rest, output)) => return Ok((rest, (*tag, output))),
+                Err(rest) if rest.len() < furthest.len() => furthest = rest,
+                Err(_) => {}
+            }
+        }
+        Err(furthest)
+    }
+}
+
+fn attempt<'a, P, A>(parser: P) -> impl Parser<'a, A>
+where
+    P: Parser<'a, A>,
+{
+    move |input| parser.parse(input).map_err(|_| input)
+}
+
+fn optional<'a, P, A>(parser: P) -> impl Parser<'a, Option<A>>
+where
+    P: Parser<'a, A>,
+{
+    move |input| match parser.parse(input) {
+        Ok((rest, value)) => Ok((rest, Some(value))),
+        Err(_) => Ok((input, None)),
+    }
+}
+
+// Drops the error detail for callers that only care whether it matched.
+fn parse_to_option<'a, A>(result: ParseResult<'a, A>) -> Option<(&'a str, A)> {
+    result.ok()
+}
+
+// The way back: an `Option`-returning function as a parser, where `None`
+// fails without consuming anything.
+fn or_none<'a, F, A>(f: F) -> impl Parser<'a, A>
+where
+    F: Fn(&'a str) -> Option<(&'a str, A)>,
+{
+    move |input| f(input).ok_or(input)
+}
+
+fn and_then<'a, P, F, A, B, NextP>(parser: P, f: F) -> impl Parser<'a, B>
+where
+    P: Parser<'a, A>,
+    NextP: Parser<'a, B>,
+    F: Fn(A) -> NextP,
+{
+    move |input| match parser.parse(input) {
+        Ok((next_input, result)) => f(result).parse(next_input),
+        Err(err) => Err(err),
+    }
+}
+
+// Like `and_then`, but the captured value is kept alongside the result of
+// the parser built from it.
+fn with_context<'a, P, F, C, B>(capture: P, use_it: F) -> impl Parser<'a, (C, B)>
+where
+    P: Parser<'a, C>,
+    F: Fn(&C) -> BoxedParser<'a, B>,
+{
+    move |input| {
+        let (rest, captured) = capture.parse(input)?;
+        let (rest, output) = use_it(&captured).parse(rest)?;
+        Ok((rest, (captured, output)))
+    }
+}
+
+// Builds a value from the flat tuple of results produced by `from_parts`.
+trait FromParts<Parts> {
+    fn from_parts(parts: Parts) -> Self;
+}
+
+// A tuple of parsers run one after another, producing a flat tuple of
+// results rather than the nested pairs `pair` gives.
+trait Sequence<'a, Parts> {
+    fn parse_parts(&self, input: &'a str) -> ParseResult<'a, Parts>;
+}
+
+macro_rules! impl_sequence {
+    ($($parser:ident => $output:ident),+) => {
+        impl<'a, $($parser, $output),+> Sequence<'a, ($($output,)+)> for ($($parser,)+)
+        where
+            $($parser: Parser<'a, $output>),+
+        {
+            #[allow(non_snake_case)]
+            fn parse_parts(&self, input: &'a str) -> ParseResult<'a, ($($output,)+)> {
+                let ($($parser,)+) = self;
+                $(let (input, $output) = $parser.parse(input)?;)+
+                Ok((input, ($($output,)+)))
+            }
+        }
+    };
+}
+
+impl_sequence!(P1 => A);
+impl_sequence!(P1 => A, P2 => B);
+impl_sequence!(P1 => A, P2 => B, P3 => C);
+impl_sequence!(P1 => A, P2 => B, P3 => C, P4 => D);
+impl_sequence!(P1 => A, P2 => B, P3 => C, P4 => D, P5 => E);
+
+fn from_parts<'a, S, Parts, T>(parsers: S) -> impl Parser<'a, T>
+where
+    S: Sequence<'a, Parts>,
+    T: FromParts<Parts>,
+{
+    move |input| {
+        let (rest, parts) = parsers.parse_parts(input)?;
+        Ok((rest, T::from_parts(parts)))
+    }
+}
+
+// Pins a closure to the `Parser` signature so macros can build parsers
+// without spelling out the input lifetime.
+fn parser_fn<'a, A, F>(f: F) -> F
+where
+    F: Fn(&'a str) -> ParseResult<'a, A>,
+{
+    f
+}
+
+// `seq!(a = p1, _ = p2, b = p3 => expr)` runs the parsers in order, binding
+// each result to its pattern, and yields `expr`. The parser expressions are
+// evaluated on every parse, so keep them cheap.
+#[allow(unused_macros)]
+macro_rules! seq {
+    ($($binding:pat = $parser:expr),+ => $result:expr) => {
+        parser_fn(move |input| {
+            $(let (input, $binding) = Parser::parse(&$parser, input)?;)+
+            Ok((input, $result))
+        })
+    };
+}
+
+// `alt!(p1 | p2 | p3)` tries each parser in order and returns the first
+// success. On failure the error is the one that got furthest into the input.
+// A branch containing a closure must be wrapped in parentheses, since `|`
+// separates branches.
+#[allow(unused_macros)]
+macro_rules! alt {
+    (@split [$($branches:tt)*] [$($current:tt)+]) => {
+        alt!(@build $($branches)* ($($current)+))
+    };
+    (@split [$($branches:tt)*] [$($current:tt)+] |) => {
+        alt!(@build $($branches)* ($($current)+))
+    };
+    (@split [$($branches:tt)*] [$($current:tt)+] | $($rest:tt)+) => {
+        alt!(@split [$($branches)* ($($current)+)] [] $($rest)+)
+    };
+    (@split [$($branches:tt)*] [$($current:tt)*] $next:tt $($rest:tt)*) => {
+        alt!(@split [$($branches)*] [$($current)* $next] $($rest)*)
+    };
+    (@build $(($($branch:tt)+))+) => {
+        parser_fn(move |input| {
+            let mut furthest = input;
+            $(
+                match Parser::parse(&($($branch)+), input) {
+                    ok @ Ok(_) => return ok,
+                    Err(rest) if rest.len() < furthest.len() => furthest = rest,
+                    Err(_) => {}
+                }
+            )+
+            Err(furthest)
+        })
+    };
+    ($($tokens:tt)+) => {
+        alt!(@split [] [] $($tokens)+)
+    };
+}
+
+fn whitespace_char<'a>() -> impl Parser<'a, char> {
+    pred(any_char, |c| c.is_whitespace())
+}
+
+fn space1<'a>() -> impl Parser<'a, Vec<char>> {
+    one_or_more(whitespace_char())
+}
+
+fn space0<'a>() -> impl Parser<'a, Vec<char>> {
+    zero_or_more(whitespace_char())
+}
+
+fn whitespace_wrap<'a, P, A>(parser: P) -> impl Parser<'a, A>
+where
+    P: Parser<'a, A>,
+{
+    right(space0(), left(parser, space0()))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct CommentStyle {
+    line: Option<&'static str>,
+    block: Option<(&'static str, &'static str)>,
+    nested_blocks: bool,
+}
+
+// C-style `//` and `/* */` comments, with block comments not nesting.
+impl Default for CommentStyle {
+    fn default() -> Self {
+        CommentStyle {
+            line: Some("//"),
+            block: Some(("/*", "*/")),
+            nested_blocks: false,
+        }
+    }
+}
+
+// Skips whitespace and comments between the tokens of a C-like language.
+fn junk<'a>() -> impl Parser<'a, ()> {
+    junk_with(CommentStyle::default())
+}
+
+// An unterminated block comment fails at its opening delimiter.
+fn junk_with<'a>(style: CommentStyle) -> impl Parser<'a, ()> {
+    move |mut input: &'a str| loop {
+        let trimmed = input.trim_start();
+        if let Some(rest) = style.line.and_then(|open| trimmed.strip_prefix(open)) {
+            input = rest.find('\n').map_or("", |end| &rest[end + 1..]);
+        } else if let Some((open, close)) =
+            style.block.filter(|(open, _)| trimmed.starts_with(open))
+        {
+            input = skip_block_comment(trimmed, open, close, style.nested_blocks)?;
+        } else {
+            return Ok((trimmed, ()));
+        }
+    }
+}
+
+fn skip_block_comment<'a>(
+    input: &'a str,
+    open: &str,
+    close: &str,
+    nested: bool,
+) -> Result<&'a str, &'a str> {
+    let mut depth = 0;
+    let mut rest = input;
+    loop {
+        if rest.starts_with(open) && (depth == 0 || nested) {
+            depth += 1;
+            rest = &rest[open.len()..];
+        } else if rest.starts_with(close) {
+            depth -= 1;
+            rest = &rest[close.len()..];
+            if depth == 0 {
+                return Ok(rest);
+            }
+        } else {
+            let mut chars = rest.chars();
+            chars.next().ok_or(input)?;
+            rest = chars.as_str();
+        }
+    }
+}
+
+fn match_literal_ws<'a>(expected: &'a str) -> impl Parser<'a, &'a str> {
+    whitespace_wrap(match_literal(expected))
+}
+
+fn quoted_string<'a>() -> impl Parser<'a, String> {
+    right(
+        match_literal("\""),
+        left(
+            collect_string(zero_or_more(pred(any_char, |c| *c != '"'))),
+            match_literal("\""),
+        ),
+    )
+}
+
+// Replaces the five predefined XML entities, undoing `escape_attribute` and
+// `escape_text`. Any other `&` is kept as written.
+fn decode_entities(raw: &str) -> String {
+    const ENTITIES: [(&str, char); 5] = [
+        ("&amp;", '&'),
+        ("&lt;", '<'),
+        ("&gt;", '>'),
+        ("&quot;", '"'),
+        ("&apos;", '\''),
+    ];
+    let mut decoded = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match ENTITIES.iter().find(|(entity, _)| rest.starts_with(entity)) {
+            Some((entity, c)) => {
+                decoded.push(*c);
+                rest = &rest[entity.len()..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+// Either quote style, as XML allows for attribute values; the closing quote
+// must match the opening one. Entities are decoded.
+fn quoted_string_any<'a>() -> impl Parser<'a, String> {
+    and_then(pred(any_char, |c| *c == '"' || *c == '\''), |quote| {
+        left(
+            map(
+                collect_string(zero_or_more(pred(any_char, move |c| *c != quote))),
+                |raw| decode_entities(&raw),
+            ),
+            pred(any_char, move |c| *c == quote),
+        )
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ValuePart {
+    Literal(String),
+    Ref(String),
+}
+
+// A quoted value where `${name}` refers to another attribute, e.g.
+// `"a ${x} b"`. A `${` that isn't closed by `}` after an identifier fails.
+fn interpolated_value<'a>() -> impl Parser<'a, Vec<ValuePart>> {
+    let reference = map(
+        right(match_literal("${"), left(match_ident, match_literal("}"))),
+        ValuePart::Ref,
+    );
+    let literal = map(
+        collect_string(one_or_more(right(
+            not_followed_by(match_literal("${")),
+            pred(any_char, |c| *c != '"'),
+        ))),
+        ValuePart::Literal,
+    );
+    right(
+        match_literal("\""),
+        left(
+            zero_or_more(either(reference, literal)),
+            match_literal("\""),
+        ),
+    )
+}
+
+// Unlike `quoted_string`, which keeps XML attribute values verbatim, this
+// decodes `\"`, `\\`, `\n`, `\t` and `\uXXXX`. An unknown escape fails at its
+// backslash.
+fn escaped_string<'a>() -> impl Parser<'a, String> {
+    move |input: &'a str| {
+        let mut chars = input.char_indices();
+        match chars.next() {
+            Some((_, '"')) => {}
+            _ => return Err(input),
+        }
+        let mut decoded = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((&input[i + 1..], decoded)),
+                '\\' => {
+                    let escaped = match chars.next() {
+                        Some((_, '"')) => '"',
+                        Some((_, '\\')) => '\\',
+                        Some((_, 'n')) => '\n',
+                        Some((_, 't')) => '\t',
+                        Some((j, 'u')) => {
+                            let code = input
+                                .get(j + 1..j + 5)
+                                .filter(|hex| hex.chars().all(|h| h.is_ascii_hexdigit()))
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .and_then(core::char::from_u32);
+                            match code {
+                                Some(code) => {
+                                    chars.nth(3);
+                                    code
+                                }
+                                None => return Err(&input[i..]),
+                            }
+                        }
+                        _ => return Err(&input[i..]),
+                    };
+                    decoded.push(escaped);
+                }
+                c => decoded.push(c),
+            }
+        }
+        Err(&input[input.len()..])
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static WS_SKIPPING: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+    static HTML_MODE: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+    static CASELESS_TAGS: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+}
+
+// Runs `f` with a grammar mode switched on, restoring it afterwards even if
+// `f` panics.
+#[cfg(feature = "std")]
+fn with_mode<T>(
+    mode: &'static std::thread::LocalKey<core::cell::Cell<bool>>,
+    f: impl FnOnce() -> T,
+) -> T {
+    struct Restore(&'static std::thread::LocalKey<core::cell::Cell<bool>>, bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            self.0.with(|mode| mode.set(self.1));
+        }
+    }
+
+    let _restore = Restore(mode, mode.with(|mode| mode.replace(true)));
+    f()
+}
+
+fn ws_skipping() -> bool {
+    #[cfg(feature = "std")]
+    return WS_SKIPPING.with(core::cell::Cell::get);
+    #[cfg(not(feature = "std"))]
+    false
+}
+
+// Runs `f` with whitespace allowed between the tokens of a tag, so
+// `< a / >` parses like `<a/>`. Outside of it whitespace is significant.
+#[cfg(feature = "std")]
+fn with_ws_skipping<T>(f: impl FnOnce() -> T) -> T {
+    with_mode(&WS_SKIPPING, f)
+}
+
+fn html_mode() -> bool {
+    #[cfg(feature = "std")]
+    return HTML_MODE.with(core::cell::Cell::get);
+    #[cfg(not(feature = "std"))]
+    false
+}
+
+// Close tags match their open tag ignoring case, for `lowercase_tags`.
+fn caseless_tags() -> bool {
+    #[cfg(feature = "std")]
+    return CASELESS_TAGS.with(core::cell::Cell::get);
+    #[cfg(not(feature = "std"))]
+    false
+}
+
+fn ws_skip<'a>() -> impl Parser<'a, ()> {
+    move |input: &'a str| {
+        if ws_skipping() {
+            map(space0(), |_| ()).parse(input)
+        } else {
+            Ok((input, ()))
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static WARNINGS: core::cell::RefCell<Option<Vec<(usize, String)>>> =
+        const { core::cell::RefCell::new(None) };
+}
+
+// Warnings are keyed by how much input was left, so a rule that is re-parsed
+// after backtracking only reports once.
+fn push_warning(remaining: usize, message: String) {
+    #[cfg(feature = "std")]
+    WARNINGS.with(|warnings| {
+        if let Some(warnings) = warnings.borrow_mut().as_mut() {
+            let warning = (remaining, message);
+            if !warnings.contains(&warning) {
+                warnings.push(warning);
+            }
+        }
+    });
+    #[cfg(not(feature = "std"))]
+    let _ = (remaining, message);
+}
+
+// Reports `message(&output)`, if any, whenever `parser` matches. Warnings are
+// only collected under `parse_str_with_warnings` and never fail the parse.
+fn warn<'a, P, A, F>(parser: P, message: F) -> impl Parser<'a, A>
+where
+    P: Parser<'a, A>,
+    F: Fn(&A) -> Option<String>,
+{
+    move |input: &'a str| {
+        let (rest, output) = parser.parse(input)?;
+        if let Some(message) = message(&output) {
+            push_warning(input.len(), message);
+        }
+        Ok((rest, output))
+    }
+}
+
+// The rules being parsed, outermost first, and the deepest stack seen at the
+// furthest failure so far, as remaining input length.
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct ContextTrace {
+    active: Vec<&'static str>,
+    furthest: Option<(usize, Vec<&'static str>)>,
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static CONTEXT: core::cell::RefCell<Option<ContextTrace>> =
+        const { core::cell::RefCell::new(None) };
+}
+
+fn enter_context(name: &'static str) {
+    #[cfg(feature = "std")]
+    CONTEXT.with(|trace| {
+        if let Some(trace) = trace.borrow_mut().as_mut() {
+            trace.active.push(name);
+        }
+    });
+    #[cfg(not(feature = "std"))]
+    let _ = name;
+}
+
+fn leave_context(failed_at: Option<usize>) {
+    #[cfg(feature = "std")]
+    CONTEXT.with(|trace| {
+        if let Some(trace) = trace.borrow_mut().as_mut() {
+            if let Some(remaining) = failed_at {
+                let further = match &trace.furthest {
+                    Some((seen, stack)) => {
+                        remaining < *seen
+                            || (remaining == *seen && trace.active.len() > stack.len())
+                    }
+                    None => true,
+                };
+                if further {
+                    trace.furthest = Some((remaining, trace.active.clone()));
+                }
+            }
+            trace.active.pop();
+        }
+    });
+    #[cfg(not(feature = "std"))]
+    let _ = failed_at;
+}
+
+// Names the rule `parser` implements, so a failure inside it reads "while
+// parsing <name>". Only traced under `parse_str_with_context`.
+fn context<'a, P, A>(name: &'static str, parser: P) -> impl Parser<'a, A>
+where
+    P: Parser<'a, A>,
+{
+    move |input: &'a str| {
+        enter_context(name);
+        let result = parser.parse(input);
+        leave_context(result.as_ref().err().map(|rest| rest.len()));
+        result
+    }
+}
+
+fn token<'a>(expected: &'a str) -> impl Parser<'a, &'a str> {
+    right(ws_skip(), match_literal(expected))
+}
+
+// HTML also allows unquoted values, like `type=text`, which run until
+// whitespace, a character that could not appear unquoted, or the `/>` that
+// closes the tag.
+fn attr_value_lenient<'a>() -> impl Parser<'a, String> {
+    let unquoted = move |input: &'a str| {
+        let end = input
+            .char_indices()
+            .find(|&(i, c)| {
+                c.is_whitespace()
+                    || matches!(c, '"' | '\'' | '=' | '<' | '>' | '`')
+                    || input[i..].starts_with("/>")
+            })
+            .map_or(input.len(), |(i, _)| i);
+        match end {
+            0 => Err(input),
+            _ => Ok((&input[end..], input[..end].to_string())),
+        }
+    };
+    either(quoted_string_any(), unquoted)
+}
+
+fn attr_value<'a>() -> impl Parser<'a, String> {
+    move |input: &'a str| {
+        if html_mode() {
+            attr_value_lenient().parse(input)
+        } else {
+            quoted_string_any().parse(input)
+        }
+    }
+}
+
+fn attribute_pair<'a>() -> impl Parser<'a, (String, String)> {
+    let attribute = pair(
+        match_ident,
+        right(token("="), right(ws_skip(), attr_value())),
+    );
+    context("attribute", move |input| {
+        let (rest, attribute) = attribute.parse(input)?;
+        if !check_attr_limits(&attribute) {
+            return Err(input);
+        }
+        Ok((rest, attribute))
+    })
+}
+
+#[cfg(feature = "std")]
+struct AttrLimits {
+    max_name_len: Option<usize>,
+    max_value_len: Option<usize>,
+    exceeded: Option<String>,
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static ATTR_LIMITS: core::cell::RefCell<Option<AttrLimits>> =
+        const { core::cell::RefCell::new(None) };
+}
+
+// Runs `f` with `config`'s attribute length limits checked as each attribute
+// is parsed, so an oversized one stops the parse at that attribute. Also
+// returns the message for the first limit exceeded.
+#[cfg(feature = "std")]
+fn with_attr_limits<T>(config: &ParserConfig, f: impl FnOnce() -> T) -> (T, Option<String>) {
+    if config.max_attr_name_len.is_none() && config.max_attr_value_len.is_none() {
+        return (f(), None);
+    }
+    let limits = AttrLimits {
+        max_name_len: config.max_attr_name_len,
+        max_value_len: config.max_attr_value_len,
+        exceeded: None,
+    };
+    // Puts the outer limits back when dropped, so a panic in `f` doesn't
+    // leave these installed.
+    struct Restore(Option<Option<AttrLimits>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(outer) = self.0.take() {
+                ATTR_LIMITS.with(|current| current.replace(outer));
+            }
+        }
+    }
+
+    let mut restore = Restore(Some(
+        ATTR_LIMITS.with(|current| current.replace(Some(limits))),
+    ));
+    let result = f();
+    let outer = restore.0.take().unwrap_or(None);
+    let limits = ATTR_LIMITS.with(|current| current.replace(outer));
+    (result, limits.and_then(|limits| limits.exceeded))
+}
+
+fn check_attr_limits(attribute: &(String, String)) -> bool {
+    #[cfg(feature = "std")]
+    return ATTR_LIMITS.with(|limits| match limits.borrow_mut().as_mut() {
+        Some(limits) => {
+            match check_attr_len(attribute, limits.max_name_len, limits.max_value_len) {
+                Ok(()) => true,
+                Err(message) => {
+                    limits.exceeded.get_or_insert(message);
+                    false
+                }
+            }
+        }
+        None => true,
+    });
+    #[cfg(not(feature = "std"))]
+    {
+        let _ = attribute;
+        true
+    }
+}
+
+fn check_attr_len(
+    (key, value): &(String, String),
+    max_name_len: Option<usize>,
+    max_value_len: Option<usize>,
+) -> Result<(), String> {
+    let too_long = |text: &str, max: Option<usize>| max.is_some_and(|max| text.len() > max);
+    if too_long(key, max_name_len) {
+        return Err(format!("attribute name {:?} exceeds maximum length", key));
+    }
+    if too_long(value, max_value_len) {
+        return Err(format!(
+            "value of attribute {:?} exceeds maximum length",
+            key
+        ));
+    }
+    Ok(())
+}
+
+fn attributes<'a>() -> impl Parser<'a, Vec<(String, String)>> {
+    let empty_value = |(name, value): &(String, String)| {
+        if value.is_empty() {
+            Some(format!("empty attribute value for {:?}", name))
+        } else {
+            None
+        }
+    };
+    zero_or_more(right(space1(), warn(attribute_pair(), empty_value)))
+}
+
+// Letter or underscore first, then letters, digits, `-`, `.` and `:`.
+fn tag_name<'a>() -> impl Parser<'a, String> {
+    map(
+        pair(
+            pred(any_char, |c| c.is_alphabetic() || *c == '_'),
+            zero_or_more(pred(any_char, |c| {
+                c.is_alphanumeric() || *c == '-' || *c == '.' || *c == ':'
+            })),
+        ),
+        |(first, rest)| core::iter::once(first).chain(rest).collect(),
+    )
+}
+
+// `NameStartChar` from the XML 1.0 (fifth edition) `Name` production.
+fn is_xml_name_start(c: char) -> bool {
+    matches!(c,
+        ':' | 'A'..='Z' | '_' | 'a'..='z'
+        | '\u{C0}'..='\u{D6}'
+        | '\u{D8}'..='\u{F6}'
+        | '\u{F8}'..='\u{2FF}'
+        | '\u{370}'..='\u{37D}'
+        | '\u{37F}'..='\u{1FFF}'
+        | '\u{200C}'..='\u{200D}'
+        | '\u{2070}'..='\u{218F}'
+        | '\u{2C00}'..='\u{2FEF}'
+        | '\u{3001}'..='\u{D7FF}'
+        | '\u{F900}'..='\u{FDCF}'
+        | '\u{FDF0}'..='\u{FFFD}'
+        | '\u{10000}'..='\u{EFFFF}')
+}
+
+// `NameChar` from the same production.
+fn is_xml_name_char(c: char) -> bool {
+    is_xml_name_start(c)
+        || matches!(c,
+            '-' | '.' | '0'..='9' | '\u{B7}'
+            | '\u{300}'..='\u{36F}'
+            | '\u{203F}'..='\u{2040}')
+}
+
+fn xml_ident<'a>() -> impl Parser<'a, String> {
+    map(
+        pair(
+            pred(any_char, |c| is_xml_name_start(*c)),
+            zero_or_more(pred(any_char, |c| is_xml_name_char(*c))),
+        ),
+        |(first, rest)| core::iter::once(first).chain(rest).collect(),
+    )
+}
+
+fn element_start<'a>() -> impl Parser<'a, (String, Vec<(String, String)>)> {
+    let uppercase = |name: &String| {
+        if name.chars().any(char::is_uppercase) {
+            Some(format!("uppercase tag name <{}>", name))
+        } else {
+            None
+        }
+    };
+    right(
+        token("<"),
+        pair(right(ws_skip(), warn(tag_name(), uppercase)), attributes()),
+    )
+}
+
+fn empty_element_end<'a>() -> impl Parser<'a, ()> {
+    map(pair(token("/"), token(">")), |_| ())
+}
+
+#[cfg(feature = "trace-hooks")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TraceEvent {
+    Enter(String),
+    Exit(String),
+}
+
+#[cfg(feature = "trace-hooks")]
+type Tracer = Box<dyn FnMut(TraceEvent)>;
+
+#[cfg(feature = "trace-hooks")]
+std::thread_local! {
+    static TRACER: core::cell::RefCell<Option<Tracer>> = const { core::cell::RefCell::new(None) };
+}
+
+// Runs `f` with `tracer` receiving a span entry and exit for `parse_str` and
+// for every element, so nesting and timing can be logged.
+#[cfg(feature = "trace-hooks")]
+fn with_tracer<T>(tracer: impl FnMut(TraceEvent) + 'static, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Tracer>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            TRACER.with(|tracer| *tracer.borrow_mut() = self.0.take());
+        }
+    }
+
+    let outer = TRACER.with(|current| current.replace(Some(Box::new(tracer))));
+    let _restore = Restore(outer);
+    f()
+}
+
+#[cfg(feature = "trace-hooks")]
+fn emit_trace(event: TraceEvent) {
+    TRACER.with(|tracer| {
+        if let Some(tracer) = tracer.borrow_mut().as_mut() {
+            tracer(event);
+        }
+    });
+}
+
+// Emits `Enter` when created and `Exit` when dropped, so a span is closed
+// even when the parse inside it fails or backtracks.
+#[must_use]
+struct TraceSpan {
+    #[cfg(feature = "trace-hooks")]
+    name: String,
+}
+
+impl TraceSpan {
+    fn enter(name: &str) -> Self {
+        #[cfg(feature = "trace-hooks")]
+        {
+            emit_trace(TraceEvent::Enter(name.to_string()));
+            TraceSpan {
+                name: name.to_string(),
+            }
+        }
+        #[cfg(not(feature = "trace-hooks"))]
+        {
+            let _ = name;
+            TraceSpan {}
+        }
+    }
+}
+
+#[cfg(feature = "trace-hooks")]
+impl Drop for TraceSpan {
+    fn drop(&mut self) {
+        emit_trace(TraceEvent::Exit(core::mem::take(&mut self.name)));
+    }
+}
+
+fn single_element<'a>() -> impl Parser<'a, Element> {
+    map(
+        left(element_start(), empty_element_end()),
+        |(name, attributes)| Element {
+            name,
+            attributes,
+            children: vec![],
+        },
+    )
+}
+
+fn open_element<'a>() -> impl Parser<'a, Element> {
+    map(left(element_start(), token(">")), |(name, attributes)| {
+        Element {
+            name,
+            attributes,
+            children: vec![],
+        }
+    })
+}
+
+fn close_tag<'a>() -> impl Parser<'a, String> {
+    right(
+        pair(token("<"), token("/")),
+        left(right(ws_skip(), tag_name()), token(">")),
+    )
+}
+
+fn close_element<'a>(expected_name: String) -> impl Parser<'a, String> {
+    let span = format!("/{}", expected_name);
+    let close = pred(close_tag(), move |name| {
+        name == &expected_name
+            || caseless_tags() && name.to_lowercase() == expected_name.to_lowercase()
+    });
+    move |input| {
+        let _span = TraceSpan::enter(&span);
+        close.parse(input)
+    }
+}
+
+fn text<'a>() -> impl Parser<'a, String> {
+    map(
+        collect_string(one_or_more(pred(any_char, |c| *c != '<'))),
+        |raw| decode_entities(&raw),
+    )
+}
+
+// Whitespace-only text between tags is layout, not content, and is dropped.
+fn content<'a>() -> impl Parser<'a, Vec<Node>> {
+    map(
+        zero_or_more(either(
+            map(bare_element(), Node::Element),
+            map(text(), Node::Text),
+        )),
+        |nodes| {
+            nodes
+                .into_iter()
+                .filter(|node| match node {
+                    Node::Text(text) => !text.trim().is_empty(),
+                    Node::Element(_) => true,
+                })
+                .collect()
+        },
+    )
+}
+
+fn parent_element<'a>() -> impl Parser<'a, Element> {
+    and_then(open_element(), |el| {
+        map(
+            left(content(), close_element(el.name.clone())),
+            move |children| {
+                let mut el = el.clone();
+                el.children = children;
+                el
+            },
+        )
+    })
+}
+
+// `single_element` or `parent_element`, reading the start tag only once. The
+// element's trace span stays open over its children and close tag.
+fn bare_element<'a>() -> BoxedParser<'a, Element> {
+    BoxedParser::new(context("element", move |input| {
+        let (rest, (name, attributes)) = element_start().parse(input)?;
+        let _span = TraceSpan::enter(&name);
+        let mut el = Element {
+            name,
+            attributes,
+            children: vec![],
+        };
+        if let Ok((rest, ())) = empty_element_end().parse(rest) {
+            return Ok((rest, el));
+        }
+        let (rest, _) = token(">").parse(rest)?;
+        let (rest, children) = left(content(), close_element(el.name.clone())).parse(rest)?;
+        el.children = children;
+        Ok((rest, el))
+    }))
+}
+
+fn element<'a>() -> BoxedParser<'a, Element> {
+    BoxedParser::new(whitespace_wrap(bare_element()))
+}
+
+fn xml_decl<'a>() -> impl Parser<'a, Vec<(String, String)>> {
+    right(
+        match_literal("<?xml"),
+        left(attributes(), right(space0(), match_literal("?>"))),
+    )
+}
+
+// The target `xml` is reserved for the declaration, in any case, so a stray
+// second `<?xml ...?>` is an error rather than an ordinary instruction.
+fn processing_instruction<'a>() -> impl Parser<'a, (String, String)> {
+    move |input: &'a str| {
+        let (rest, target) = right(match_literal("<?"), tag_name()).parse(input)?;
+        if target.eq_ignore_ascii_case("xml") {
+            return Err(input);
+        }
+        match rest.find("?>") {
+            Some(end) => Ok((
+                &rest[end + 2..],
+                (target, rest[..end].trim_start().to_string()),
+            )),
+            None => Err(rest),
+        }
+    }
+}
+
+fn comment<'a>() -> impl Parser<'a, String> {
+    map(
+        right(
+            match_literal("<!--"),
+            left(take_until("-->"), match_literal("-->")),
+        ),
+        String::from,
+    )
+}
+
+// Returns the raw `<!DOCTYPE ...>` text. A `[...]` internal subset and quoted
+// literals may contain `>` without ending the declaration.
+fn doctype<'a>() -> impl Parser<'a, String> {
+    move |input: &'a str| {
+        let (rest, _) = match_literal("<!DOCTYPE").parse(input)?;
+        let mut in_subset = false;
+        let mut quote = None;
+        for (i, c) in rest.char_indices() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"') | (None, '\'') => quote = Some(c),
+                (None, '[') => in_subset = true,
+                (None, ']') => in_subset = false,
+                (None, '>') if !in_subset => {
+                    let end = input.len() - rest.len() + i + 1;
+                    return Ok((&input[end..], input[..end].to_string()));
+                }
+                _ => {}
+            }
+        }
+        Err(&rest[rest.len()..])
+    }
+}
+
+fn prolog<'a>() -> impl Parser<'a, ()> {
+    let misc = || zero_or_more(whitespace_wrap(processing_instruction()));
+    map(
+        left(
+            pair(
+                pair(optional(whitespace_wrap(xml_decl())), misc()),
+                pair(optional(whitespace_wrap(doctype())), misc()),
+            ),
+            space0(),
+        ),
+        |_| (),
+    )
+}
+
+fn eof(input: &str) -> ParseResult<'_, ()> {
+    if input.is_empty() {
+        Ok((input, ()))
+    } else {
+        Err(input)
+    }
+}
+
+fn parse_error(input: &str, rest: &str) -> String {
+    if rest.is_empty() {
+        String::from("unexpected end of input")
+    } else {
+        format!(
+            "parse error at byte {}: unexpected {:?}",
+            offset_in(input, rest.len()),
+            rest.chars().take(10).collect::<String>()
+        )
+    }
+}
+
+// 1-based line and column (in chars) of `remaining` within `original`.
+fn locate(original: &str, remaining: &str) -> (usize, usize) {
+    let consumed = &original[..offset_in(original, remaining.len())];
+    let line_start = consumed.rfind('\n').map_or(0, |i| i + 1);
+    (
+        consumed.matches('\n').count() + 1,
+        consumed[line_start..].chars().count() + 1,
+    )
+}
+
+// A position in `input` that keeps its line and column (1-based, columns in
+// chars) up to date as it moves, by scanning only the text moved over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Cursor<'a> {
+    input: &'a str,
+    offset: usize,
+    line: usize,
+    col: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str) -> Self {
+        Cursor {
+            input,
+            offset: 0,
+            line: 1,
+            col: 1,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.offset..]
+    }
+
+    // Moves forward to where `rest` starts, which must be a suffix of the
+    // current rest.
+    fn advance_to(self, rest: &'a str) -> Self {
+        let end = self.input.len() - rest.len();
+        let mut cursor = self;
+        for c in self.input[self.offset..end].chars() {
+            if c == '\n' {
+                cursor.line += 1;
+                cursor.col = 1;
+            } else {
+                cursor.col += 1;
+            }
+        }
+        cursor.offset = end;
+        cursor
+    }
+
+    // Runs `parser` at the cursor. Either way the cursor comes back moved to
+    // where the parser stopped.
+    fn parse<P, A>(self, parser: &P) -> Result<(Self, A), Self>
+    where
+        P: Parser<'a, A>,
+    {
+        match parser.parse(self.rest()) {
+            Ok((rest, output)) => Ok((self.advance_to(rest), output)),
+            Err(rest) => Err(self.advance_to(rest)),
+        }
+    }
+}
+
+// The failing line, clipped to `context` chars either side of the failure,
+// with a caret under the column:
+//
+//     1:6
+//     <a x=1/>
+//          ^
+fn error_snippet(original: &str, remaining: &str, context: usize) -> String {
+    let (line, column) = locate(original, remaining);
+    let offset = offset_in(original, remaining.len());
+    let line_start = original[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = original[offset..]
+        .find('\n')
+        .map_or(original.len(), |i| offset + i);
+    let before: Vec<char> = original[line_start..offset].chars().collect();
+    let before = &before[before.len().saturating_sub(context)..];
+    let after: String = original[offset..line_end].chars().take(context).collect();
+    format!(
+        "{}:{}\n{}{}\n{}^",
+        line,
+        column,
+        before.iter().collect::<String>(),
+        after,
+        " ".repeat(before.len())
+    )
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    offset: usize,
+    message: String,
+    // Rules that were being parsed, innermost first.
+    context: Vec<&'static str>,
+}
+
+impl ParseError {
+    fn new(input: &str, rest: &str) -> Self {
+        Self::at(input, offset_in(input, rest.len()))
+    }
+
+    // At byte `offset` of `input`.
+    fn at(input: &str, offset: usize) -> Self {
+        ParseError {
+            offset,
+            message: parse_error(input, &input[offset..]),
+            context: vec![],
+        }
+    }
+
+    // For checks on the document as a whole rather than at a position in it.
+    fn document(message: String) -> Self {
+        ParseError {
+            offset: 0,
+            message,
+            context: vec![],
+        }
+    }
+}
+
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        for (i, name) in self.context.iter().enumerate() {
+            let separator = if i + 1 == self.context.len() {
+                ": "
+            } else {
+                ", "
+            };
+            write!(f, "while parsing {}{}", name, separator)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+// `Box<dyn Error>` then converts from `ParseError` through std's blanket
+// `From` impl, so `?` works in functions returning it.
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Debug, Default)]
+struct ParserConfig {
+    trim_text: bool,
+    max_input_len: Option<usize>,
+    unique_attributes: bool,
+    // Accept HTML-style unquoted attribute values. Without the `std` feature
+    // parsing fails instead.
+    html: bool,
+    // Lowercase names once parsed. With `lowercase_tags` a close tag matches
+    // its open tag ignoring case; that needs the `std` feature, and without
+    // it parsing fails instead.
+    lowercase_tags: bool,
+    lowercase_attrs: bool,
+    // Replace tabs and line breaks in attribute values with spaces, as XML
+    // does for non-CDATA attributes.
+    normalize_attr_whitespace: bool,
+    // Byte limits on each attribute name and value.
+    max_attr_name_len: Option<usize>,
+    max_attr_value_len: Option<usize>,
+}
+
+impl ParserConfig {
+    fn check_input_len(&self, input: &str) -> Result<(), String> {
+        match self.max_input_len {
+            Some(max) if input.len() > max => Err(String::from("input exceeds maximum length")),
+            _ => Ok(()),
+        }
+    }
+
+    fn rewrite_tag(&self, name: &mut String, attributes: &mut [(String, String)]) {
+        if self.lowercase_tags {
+            *name = name.to_lowercase();
+        }
+        if self.lowercase_attrs {
+            for (key, _) in attributes.iter_mut() {
+                *key = key.to_lowercase();
+            }
+        }
+        if self.normalize_attr_whitespace {
+            // A line ending counts once: `\r\n` and a lone `\r` become `\n`
+            // before whitespace turns into spaces.
+            for (_, value) in attributes.iter_mut() {
+                *value = value.replace("\r\n", "\n").replace(['\t', '\n', '\r'], " ");
+            }
+        }
+    }
+
+    fn check_attributes(&self, attributes: &[(String, String)]) -> Result<(), String> {
+        attributes.iter().try_for_each(|attribute| {
+            check_attr_len(attribute, self.max_attr_name_len, self.max_attr_value_len)
+        })
+    }
+
+    // Without `std` there is no thread-local to check limits during parsing,
+    // so they are checked on the finished tree instead.
+    #[cfg(not(feature = "std"))]
+    fn check_attribute_lens(&self, el: &Element) -> Result<(), String> {
+        self.check_attributes(&el.attributes)?;
+        el.child_elements()
+            .try_for_each(|child| self.check_attribute_lens(child))
+    }
+}
+
+fn validate_unique_attributes(el: &Element) -> Result<(), String> {
+    check_unique_attributes(&el.name, &el.attributes)?;
+    el.child_elements().try_for_each(validate_unique_attributes)
+}
+
+fn check_unique_attributes(name: &str, attributes: &[(String, String)]) -> Result<(), String> {
+    for (i, (key, _)) in attributes.iter().enumerate() {
+        if attributes[..i].iter().any(|(seen, _)| seen == key) {
+            return Err(format!("duplicate attribute {:?} on <{}>", key, name));
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_str(input: &str) -> Result<Element, String> {
+    Element::try_from(input).map_err(|err| err.to_string())
+}
+
+fn parse_str_with(input: &str, config: &ParserConfig) -> Result<Element, String> {
+    parse_document(input, config).map_err(|err| err.to_string())
+}
+
+fn document<'a>() -> impl Parser<'a, Element> {
+    right(prolog(), left(element(), eof))
+}
+
+fn parse_document(input: &str, config: &ParserConfig) -> Result<Element, ParseError> {
+    config
+        .check_input_len(input)
+        .map_err(ParseError::document)?;
+    #[cfg(feature = "std")]
+    let parsed = {
+        let parse = || {
+            if config.lowercase_tags {
+                with_mode(&CASELESS_TAGS, || document().parse(input))
+            } else {
+                document().parse(input)
+            }
+        };
+        let (parsed, exceeded) = with_attr_limits(config, || {
+            if config.html {
+                with_mode(&HTML_MODE, parse)
+            } else {
+                parse()
+            }
+        });
+        if let Some(message) = exceeded {
+            return Err(ParseError::document(message));
+        }
+        parsed
+    };
+    #[cfg(not(feature = "std"))]
+    let parsed = if config.html {
+        return Err(ParseError::document(String::from(
+            "html mode requires the std feature",
+        )));
+    } else if config.lowercase_tags {
+        return Err(ParseError::document(String::from(
+            "lowercase_tags requires the std feature",
+        )));
+    } else {
+        document().parse(input)
+    };
+    let mut element = match parsed {
+        Ok((_, element)) => element,
+        Err(rest) => return Err(ParseError::new(input, rest)),
+    };
+    #[cfg(not(feature = "std"))]
+    config
+        .check_attribute_lens(&element)
+        .map_err(ParseError::document)?;
+    if config.trim_text {
+        element.walk_mut(&mut |el, _| {
+            for child in &mut el.children {
+                if let Node::Text(text) = child {
+                    *text = collapse_whitespace(text);
+                }
+            }
+        });
+    }
+    if config.lowercase_tags || config.lowercase_attrs || config.normalize_attr_whitespace {
+        element.walk_mut(&mut |el, _| config.rewrite_tag(&mut el.name, &mut el.attributes));
+    }
+    if config.unique_attributes {
+        validate_unique_attributes(&element).map_err(ParseError::document)?;
+    }
+    Ok(element)
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ParseStats {
+    elapsed: core::time::Duration,
+    bytes: usize,
+    nodes: usize,
+    max_depth: usize,
+}
+
+// `elapsed` covers the parse alone, not the counting afterwards.
+#[cfg(feature = "std")]
+fn parse_stats(input: &str) -> Result<(Element, ParseStats), String> {
+    let start = std::time::Instant::now();
+    let element = parse_str(input)?;
+    let elapsed = start.elapsed();
+    let stats = ParseStats {
+        elapsed,
+        bytes: input.len(),
+        nodes: element.node_count(),
+        max_depth: element.depth(),
+    };
+    Ok((element, stats))
+}
+
+// Parses like `parse_str`, but the error is reported where parsing got
+// furthest, together with the rules that were being parsed there.
+#[cfg(feature = "std")]
+fn parse_str_with_context(input: &str) -> Result<Element, ParseError> {
+    let outer = CONTEXT.with(|trace| trace.replace(Some(ContextTrace::default())));
+    let result = document().parse(input);
+    let trace = CONTEXT
+        .with(|trace| trace.replace(outer))
+        .unwrap_or_default();
+    result
+        .map(|(_, element)| element)
+        .map_err(|rest| match trace.furthest {
+            Some((remaining, stack)) if remaining <= rest.len() => {
+                let mut error = ParseError::at(input, offset_in(input, remaining));
+                error.context = stack.into_iter().rev().collect();
+                error
+            }
+            _ => ParseError::new(input, rest),
+        })
+}
+
+// Parses like `parse_str`, also returning the non-fatal warnings raised by
+// `warn` rules in document order.
+#[cfg(feature = "std")]
+fn parse_str_with_warnings(input: &str) -> Result<(Element, Vec<String>), String> {
+    let outer = WARNINGS.with(|warnings| warnings.replace(Some(Vec::new())));
+    let result = parse_str(input);
+    let mut warnings = WARNINGS
+        .with(|warnings| warnings.replace(outer))
+        .unwrap_or_default();
+    warnings.sort_by_key(|&(remaining, _)| core::cmp::Reverse(remaining));
+    let warnings = warnings.into_iter().map(|(_, message)| message).collect();
+    result.map(|element| (element, warnings))
+}
+
+// Each text node of the document with its byte span in `input`, in
+// document order. `Node::Text` keeps no position, so the spans come from the
+// event scan rather than from a parsed `Element`.
+fn text_with_spans(input: &str) -> Result<Vec<(String, usize, usize)>, String> {
+    let mut spans = Vec::new();
+    parse_spanned_events_with(input, &ParserConfig::default(), |event, (start, end)| {
+        if let Event::Text(_) = event {
+            if !input[start..end].trim().is_empty() {
+                spans.push((input[start..end].to_string(), start, end));
+            }
+        }
+    })?;
+    Ok(spans)
+}
+
+// Unlike `parse_str`, whatever follows the root element is handed back
+// untouched, including any whitespace.
+fn parse_partial(input: &str) -> Result<(Element, &str), String> {
+    match right(prolog(), bare_element()).parse(input) {
+        Ok((rest, element)) => Ok((element, rest)),
+        Err(rest) => Err(parse_error(input, rest)),
+    }
+}
+
+// Always yields a tree. Markup that cannot be parsed is kept verbatim inside a
+// `#error` element and reported, and parsing resumes at the next `<`.
+fn parse_with_recovery(input: &str) -> (Element, Vec<ParseError>) {
+    let mut errors = Vec::new();
+    let rest = match prolog().parse(input) {
+        Ok((rest, ())) | Err(rest) => rest,
+    };
+    let root = match recover_element(input, rest, &mut errors) {
+        Some((rest, root)) => {
+            if !rest.trim().is_empty() {
+                errors.push(ParseError::new(input, rest.trim_start()));
+            }
+            root
+        }
+        None => {
+            errors.push(ParseError::new(input, rest));
+            error_element(rest)
+        }
+    };
+    (root, errors)
+}
+
+fn error_element(skipped: &str) -> Element {
+    let mut el = Element::builder("#error");
+    if !skipped.is_empty() {
+        el = el.text(skipped);
+    }
+    el.build()
+}
+
+fn recover_element<'a>(
+    input: &str,
+    rest: &'a str,
+    errors: &mut Vec<ParseError>,
+) -> Option<(&'a str, Element)> {
+    if let Ok(parsed) = single_element().parse(rest) {
+        return Some(parsed);
+    }
+    let (mut rest, mut el) = open_element().parse(rest).ok()?;
+    loop {
+        if let Ok((next, _)) = close_element(el.name.clone()).parse(rest) {
+            return Some((next, el));
+        }
+        if rest.is_empty() {
+            errors.push(ParseError::new(input, rest));
+            return Some((rest, el));
+        }
+        if let Some((next, child)) = recover_element(input, rest, errors) {
+            el.children.push(Node::Element(child));
+            rest = next;
+        } else if let Ok((next, text)) = text().parse(rest) {
+            if !text.trim().is_empty() {
+                el.children.push(Node::Text(text));
+            }
+            rest = next;
+        } else {
+            errors.push(ParseError::new(input, rest));
+            let skip = rest[1..].find('<').map_or(rest.len(), |i| i + 1);
+            el.children
+                .push(Node::Element(error_element(&rest[..skip])));
+            rest = &rest[skip..];
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Event {
+    Open {
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+    Close {
+        name: String,
+    },
+    Text(String),
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Paths look like `/a/b[2]`: the root's name, then each child's name (or
+// `#text`) with its index among its parent's children.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Change {
+    AttrChanged {
+        path: String,
+        name: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    ChildAdded {
+        path: String,
+        node: Node,
+    },
+    ChildRemoved {
+        path: String,
+    },
+    TextChanged {
+        path: String,
+        old: String,
+        new: String,
+    },
+}
+
+// Children are compared by position, so an insertion in the middle shows up
+// as changes to every later child. Differently named elements at the same
+// position are a removal plus an addition.
+fn diff(old: &Element, new: &Element) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let path = format!("/{}", old.name);
+    if old.name == new.name {
+        diff_at(&path, old, new, &mut changes);
+    } else {
+        changes.push(Change::ChildRemoved { path: path.clone() });
+        changes.push(Change::ChildAdded {
+            path: format!("/{}", new.name),
+            node: Node::Element(new.clone()),
+        });
+    }
+    changes
+}
+
+fn diff_at(path: &str, old: &Element, new: &Element, changes: &mut Vec<Change>) {
+    let lookup = |el: &Element, name: &str| {
+        el.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    };
+    let mut seen: Vec<&String> = Vec::new();
+    for (name, _) in old.attributes.iter().chain(&new.attributes) {
+        if seen.contains(&name) {
+            continue;
+        }
+        seen.push(name);
+        let (old_value, new_value) = (lookup(old, name), lookup(new, name));
+        if old_value != new_value {
+            changes.push(Change::AttrChanged {
+                path: path.to_string(),
+                name: name.clone(),
+                old: old_value,
+                new: new_value,
+            });
+        }
+    }
+
+    let child_path = |i: usize, node: &Node| match node {
+        Node::Element(el) => format!("{}/{}[{}]", path, el.name, i),
+        Node::Text(_) => format!("{}/#text[{}]", path, i),
+    };
+    for i in 0..old.children.len().max(new.children.len()) {
+        match (old.children.get(i), new.children.get(i)) {
+            (Some(Node::Element(a)), Some(Node::Element(b))) if a.name == b.name => {
+                diff_at(&child_path(i, &old.children[i]), a, b, changes)
+            }
+            (Some(Node::Text(a)), Some(Node::Text(b))) => {
+                if a != b {
+                    changes.push(Change::TextChanged {
+                        path: child_path(i, &old.children[i]),
+                        old: a.clone(),
+                        new: b.clone(),
+                    });
+                }
+            }
+            (a, b) => {
+                if let Some(a) = a {
+                    changes.push(Change::ChildRemoved {
+                        path: child_path(i, a),
+                    });
+                }
+                if let Some(b) = b {
+                    changes.push(Change::ChildAdded {
+                        path: child_path(i, b),
+                        node: b.clone(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+// Canonical form for comparing trees: attributes sorted by name, adjacent
+// text merged and whitespace-collapsed, and blank text dropped.
+fn normalize(el: Element) -> Element {
+    el.transform(&|mut el: Element| {
+        el.attributes.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut children = Vec::with_capacity(el.children.len());
+        for child in el.children {
+            match (children.last_mut(), child) {
+                (Some(Node::Text(prev)), Node::Text(text)) => prev.push_str(&text),
+                (_, child) => children.push(child),
+            }
+        }
+        el.children = children
+            .into_iter()
+            .filter_map(|child| match child {
+                Node::Text(text) => {
+                    let text = collapse_whitespace(&text);
+                    if text.is_empty() {
+                        None
+                    } else {
+                        Some(Node::Text(text))
+                    }
+                }
+                el => Some(el),
+            })
+            .collect();
+        el
+    })
+}
+
+fn parse_events<F: FnMut(Event)>(input: &str, handler: F) -> Result<(), String> {
+    parse_events_with(input, &ParserConfig::default(), handler)
+}
+
+fn parse_events_with<F: FnMut(Event)>(
+    input: &str,
+    config: &ParserConfig,
+    mut handler: F,
+) -> Result<(), String> {
+    parse_spanned_events_with(input, config, |event, _| handler(event))
+}
+
+// Like `parse_events_with`, also passing the byte span of `input` each event
+// was read from. A self-closing tag's `Close` shares its `Open` span.
+fn parse_spanned_events_with<F: FnMut(Event, (usize, usize))>(
+    input: &str,
+    config: &ParserConfig,
+    handler: F,
+) -> Result<(), String> {
+    config.check_input_len(input)?;
+    let scan = || scan_events(input, config, handler);
+    #[cfg(feature = "std")]
+    return if config.html {
+        with_mode(&HTML_MODE, scan)
+    } else {
+        scan()
+    };
+    #[cfg(not(feature = "std"))]
+    if config.html {
+        Err(String::from("html mode requires the std feature"))
+    } else {
+        scan()
+    }
+}
+
+fn scan_events<F: FnMut(Event, (usize, usize))>(
+    input: &str,
+    config: &ParserConfig,
+    mut handler: F,
+) -> Result<(), String> {
+    let open_tag = pair(
+        element_start(),
+        either(
+            map(empty_element_end(), |_| true),
+            map(token(">"), |_| false),
+        ),
+    );
+    let open_tag = spanned(input, open_tag);
+    let close_tag = spanned(input, close_tag());
+    let text_parser = spanned(input, text());
+    let mut open = Vec::new();
+    let mut rest = match prolog().parse(input) {
+        Ok((rest, ())) => rest,
+        Err(rest) => return Err(parse_error(input, rest)),
+    };
+    loop {
+        if let Ok((next, (((mut name, mut attributes), self_closing), span))) = open_tag.parse(rest)
+        {
+            config.check_attributes(&attributes)?;
+            config.rewrite_tag(&mut name, &mut attributes);
+            if config.unique_attributes {
+                check_unique_attributes(&name, &attributes)?;
+            }
+            handler(
+                Event::Open {
+                    name: name.clone(),
+                    attributes,
+                },
+                span,
+            );
+            if self_closing {
+                handler(Event::Close { name }, span);
+            } else {
+                open.push(name);
+            }
+            rest = next;
+        } else if open.is_empty() {
+            return Err(parse_error(input, rest));
+        } else if let Ok((next, (mut name, span))) = close_tag.parse(rest) {
+            config.rewrite_tag(&mut name, &mut []);
+            if open.last() != Some(&name) {
+                return Err(parse_error(input, rest));
+            }
+            open.pop();
+            handler(Event::Close { name }, span);
+            rest = next;
+        } else if let Ok((next, (text, span))) = text_parser.parse(rest) {
+            if !config.trim_text {
+                handler(Event::Text(text), span);
+            } else if !text.trim().is_empty() {
+                handler(Event::Text(collapse_whitespace(&text)), span);
+            }
+            rest = next;
+        } else {
+            return Err(parse_error(input, rest));
+        }
+        if open.is_empty() {
+            break;
+        }
+    }
+    match rest.trim_start() {
+        "" => Ok(()),
+        trailing => Err(parse_error(input, trailing)),
+    }
+}
+
+fn escape_attribute(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn to_xml(element: &Element) -> String {
+    let mut xml = format!("<{}", element.name);
+    for (key, value) in &element.attributes {
+        xml.push_str(&format!(" {}=\"{}\"", key, escape_attribute(value)));
+    }
+    if element.children.is_empty() {
+        xml.push_str("/>");
+    } else {
+        xml.push('>');
+        for child in &element.children {
+            match child {
+                Node::Element(el) => xml.push_str(&to_xml(el)),
+                Node::Text(text) => xml.push_str(&escape_text(text)),
+            }
+        }
+        xml.push_str(&format!("</{}>", element.name));
+    }
+    xml
+}
+
+// Same output as `to_xml`, written piece by piece instead of built up in
+// one `String`.
+#[cfg(feature = "std")]
+fn write_xml<W: std::io::Write>(element: &Element, w: &mut W) -> std::io::Result<()> {
+    write!(w, "<{}", element.name)?;
+    for (key, value) in &element.attributes {
+        write!(w, " {}=\"{}\"", key, escape_attribute(value))?;
+    }
+    if element.children.is_empty() {
+        return w.write_all(b"/>");
+    }
+    w.write_all(b">")?;
+    for child in &element.children {
+        match child {
+            Node::Element(el) => write_xml(el, w)?,
+            Node::Text(text) => w.write_all(escape_text(text).as_bytes())?,
+        }
+    }
+    write!(w, "</{}>", element.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Asserts that `parser` succeeds on `input` with `expected`, leaving
+    // `rest` unconsumed.
+    #[track_caller]
+    fn assert_parses<'a, A>(parser: impl Parser<'a, A>, input: &'a str, expected: A, rest: &str)
+    where
+        A: PartialEq + core::fmt::Debug,
+    {
+        assert_eq!(parser.parse(input), Ok((rest, expected)));
+    }
+
+    #[test]
+    fn test_letter() {
+        let c = '\u{1F601}';
+        let phrase = "\u{1F601} smile";
+        assert_eq!(match_letter(c, phrase), Ok((" smile", ())));
+    }
+
+    #[test]
+    fn test_literal() {
+        let lit = "\u{1F601}";
+        assert_parses(match_literal(lit), "\u{1F601} smile", lit, " smile");
+    }
+
+    #[test]
+    fn test_ident() {
+        assert_parses(match_ident, "demo-id>", String::from("demo-id"), ">");
+    }
+
+    #[test]
+    fn test_pair() {
+        let less_parser = match_literal("<");
+        let great_parser = match_literal(">");
+        assert_parses(
+            pair(pair(less_parser, match_ident), great_parser),
+            "<demo-id>",
+            (("<", String::from("demo-id")), ">"),
+            "",
+        );
+    }
+
+    #[test]
+    fn test_zero_or_more() {
+        #[derive(Debug, PartialEq, Eq)]
+        struct Ident {
+            val: String,
+        }
+
+        let phrase = "<demo-id><kaspa><xxx>";
+        let less_parser = match_literal("<");
+        let great_parser = match_literal(">");
+        let ident_parser = map(match_ident, |id| Ident { val: id });
+        let pair_parser = pair(pair(less_parser, ident_parser), great_parser);
+        assert_eq!(
+            zero_or_more(pair_parser).parse(phrase),
+            Ok((
+                "",
+                vec![
+                    (
+                        (
+                            "<",
+                            Ident {
+                                val: String::from("demo-id")
+                            }
+                        ),
+                        ">"
+                    ),
+                    (
+                        (
+                            "<",
                             Ident {
                                 val: String::from("kaspa")
                             }
@@ -188,16 +3130,2354 @@ mod tests {
                         ">"
                     ),
                     (
-                        (
-                            "<",
-                            Ident {
-                                val: String::from("xxx")
-                            }
-                        ),
-                        ">"
+                        (
+                            "<",
+                            Ident {
+                                val: String::from("xxx")
+                            }
+                        ),
+                        ">"
+                    )
+                ]
+            ))
+        )
+    }
+
+    #[test]
+    fn test_any_char() {
+        assert_eq!(any_char("\u{1F601} smile"), Ok((" smile", '\u{1F601}')));
+        assert_eq!(any_char(""), Err(""));
+    }
+
+    #[test]
+    fn test_pred() {
+        let parser = pred(any_char, |c| *c == 'o');
+        assert_eq!(parser.parse("omg"), Ok(("mg", 'o')));
+        assert_eq!(parser.parse("lol"), Err("lol"));
+    }
+
+    #[test]
+    fn test_char_range() {
+        let digit = char_range('0', '9');
+        assert_eq!(digit.parse("5x"), Ok(("x", '5')));
+        assert_eq!(digit.parse("x5"), Err("x5"));
+        assert_eq!(char_range('z', 'a').parse("m"), Err("m"));
+    }
+
+    #[test]
+    fn test_tag_name() {
+        assert_eq!(
+            tag_name().parse("x.y:z-1>"),
+            Ok((">", String::from("x.y:z-1")))
+        );
+        assert_eq!(tag_name().parse("_a b"), Ok((" b", String::from("_a"))));
+        assert_eq!(tag_name().parse("-bad"), Err("-bad"));
+        assert_eq!(tag_name().parse("1bad"), Err("1bad"));
+    }
+
+    #[test]
+    fn test_quoted_string() {
+        assert_eq!(
+            quoted_string().parse("\"Hello Joe!\""),
+            Ok(("", String::from("Hello Joe!")))
+        );
+    }
+
+    #[test]
+    fn test_attributes() {
+        assert_eq!(
+            attributes().parse(" one=\"1\" two=\"2\""),
+            Ok((
+                "",
+                vec![
+                    (String::from("one"), String::from("1")),
+                    (String::from("two"), String::from("2"))
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_single_element() {
+        assert_eq!(
+            single_element().parse("<div class=\"float\"/>"),
+            Ok((
+                "",
+                Element {
+                    name: String::from("div"),
+                    attributes: vec![(String::from("class"), String::from("float"))],
+                    children: vec![],
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_element() {
+        let doc = r#"
+            <top label="Top">
+                <semi-bottom label="Bottom"/>
+                <x.y:middle>
+                    <bottom label="Another bottom"/>
+                </x.y:middle>
+            </top>"#;
+        let parsed_doc = Element {
+            name: String::from("top"),
+            attributes: vec![(String::from("label"), String::from("Top"))],
+            children: vec![
+                Node::Element(Element {
+                    name: String::from("semi-bottom"),
+                    attributes: vec![(String::from("label"), String::from("Bottom"))],
+                    children: vec![],
+                }),
+                Node::Element(Element {
+                    name: String::from("x.y:middle"),
+                    attributes: vec![],
+                    children: vec![Node::Element(Element {
+                        name: String::from("bottom"),
+                        attributes: vec![(String::from("label"), String::from("Another bottom"))],
+                        children: vec![],
+                    })],
+                }),
+            ],
+        };
+        assert_eq!(element().parse(doc), Ok(("", parsed_doc)));
+    }
+
+    #[test]
+    fn test_mismatched_closing_tag() {
+        let doc = "<top><bottom/></middle>";
+        assert_eq!(element().parse(doc), Err("</middle>"));
+    }
+
+    #[test]
+    fn test_to_xml() {
+        let el = Element {
+            name: String::from("a"),
+            attributes: vec![(String::from("title"), String::from("x < \"y\" & z"))],
+            children: vec![
+                Node::Element(Element {
+                    name: String::from("b"),
+                    attributes: vec![],
+                    children: vec![],
+                }),
+                Node::Text(String::from(" 1 < 2 & 3 > 2")),
+            ],
+        };
+        assert_eq!(
+            to_xml(&el),
+            "<a title=\"x &lt; &quot;y&quot; &amp; z\"><b/> 1 &lt; 2 &amp; 3 &gt; 2</a>"
+        );
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(
+            parse_str("<a x=\"a&amp;b&quot;\" y='&apos;&lt;'>1 &lt; 2 &gt; 0 &amp; &copy;</a>"),
+            Ok(Element::builder("a")
+                .attr("x", "a&b\"")
+                .attr("y", "'<")
+                .text("1 < 2 > 0 & &copy;")
+                .build())
+        );
+        let el = Element::builder("a")
+            .attr("x", "<&\">")
+            .text("t < u & v")
+            .build();
+        assert_eq!(parse_str(&to_xml(&el)), Ok(el));
+    }
+
+    #[test]
+    fn test_display() {
+        let (_, el) = element().parse("<a id=\"1\"><b/><c x=\"&\"/></a>").unwrap();
+        assert_eq!(format!("{}", el), to_xml(&el));
+        assert_eq!(format!("{}", el), "<a id=\"1\"><b/><c x=\"&amp;\"/></a>");
+    }
+
+    #[test]
+    fn test_parse_str() {
+        assert_eq!(
+            parse_str(" <a><b/></a> ").map(|el| el.children.len()),
+            Ok(1)
+        );
+        assert_eq!(
+            parse_str("<a/>junk"),
+            Err(String::from("parse error at byte 4: unexpected \"junk\""))
+        );
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            "<a/>".parse::<Element>(),
+            Ok(Element {
+                name: String::from("a"),
+                attributes: vec![],
+                children: vec![],
+            })
+        );
+        let err = "<a>".parse::<Element>().unwrap_err();
+        assert_eq!(format!("{}", err), "unexpected end of input");
+    }
+
+    #[test]
+    fn test_try_from_str() {
+        assert_eq!(
+            Element::try_from("<a/>"),
+            Ok(Element {
+                name: String::from("a"),
+                attributes: vec![],
+                children: vec![],
+            })
+        );
+        let err: ParseError = Element::try_from("<a/>x").unwrap_err();
+        assert_eq!(err, ParseError::new("<a/>x", "x"));
+        assert_eq!(err.offset, 4);
+    }
+
+    #[test]
+    fn test_set_and_remove_attr() {
+        let mut el = Element::builder("a").attr("x", "1").attr("y", "2").build();
+        el.set_attr("z", "3");
+        el.set_attr("x", "4");
+        assert_eq!(
+            el.attributes,
+            vec![
+                (String::from("x"), String::from("4")),
+                (String::from("y"), String::from("2")),
+                (String::from("z"), String::from("3")),
+            ]
+        );
+        assert_eq!(el.remove_attr("y"), Some(String::from("2")));
+        assert_eq!(el.remove_attr("y"), None);
+        assert_eq!(
+            el.attributes,
+            vec![
+                (String::from("x"), String::from("4")),
+                (String::from("z"), String::from("3")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_node_count_and_depth() {
+        let leaf = |name: &str| Element {
+            name: String::from(name),
+            attributes: vec![],
+            children: vec![],
+        };
+        assert_eq!(leaf("a").node_count(), 1);
+        assert_eq!(leaf("a").depth(), 1);
+
+        let tree = Element {
+            name: String::from("root"),
+            attributes: vec![],
+            children: vec![
+                Node::Element(leaf("a")),
+                Node::Text(String::from("text")),
+                Node::Element(Element {
+                    name: String::from("b"),
+                    attributes: vec![],
+                    children: vec![Node::Element(leaf("c")), Node::Element(leaf("d"))],
+                }),
+            ],
+        };
+        assert_eq!(tree.node_count(), 5);
+        assert_eq!(tree.depth(), 3);
+    }
+
+    #[test]
+    fn test_walk() {
+        let (_, tree) = element().parse("<a><b><c/></b><d/></a>").unwrap();
+        let mut visited = Vec::new();
+        tree.walk(&mut |el, depth| visited.push((el.name.clone(), depth)));
+        assert_eq!(
+            visited,
+            vec![
+                (String::from("a"), 0),
+                (String::from("b"), 1),
+                (String::from("c"), 2),
+                (String::from("d"), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_walk_mut() {
+        let (_, mut tree) = element().parse("<a><b/><c/></a>").unwrap();
+        tree.walk_mut(&mut |el, depth| {
+            el.attributes
+                .push((String::from("depth"), depth.to_string()))
+        });
+        assert_eq!(
+            to_xml(&tree),
+            "<a depth=\"0\"><b depth=\"1\"/><c depth=\"1\"/></a>"
+        );
+    }
+
+    #[test]
+    fn test_transform() {
+        let (_, tree) = element().parse("<a><b><c/></b></a>").unwrap();
+        let upper = tree.transform(&|mut el: Element| {
+            el.name = el.name.to_uppercase();
+            el
+        });
+        assert_eq!(to_xml(&upper), "<A><B><C/></B></A>");
+
+        let (_, tree) = element().parse("<a><b/><c/></a>").unwrap();
+        let visited = core::cell::RefCell::new(Vec::new());
+        tree.transform(&|el: Element| {
+            visited.borrow_mut().push(el.name.clone());
+            el
+        });
+        assert_eq!(visited.into_inner(), vec!["b", "c", "a"]);
+    }
+
+    #[test]
+    fn test_prune() {
+        let (_, tree) = element()
+            .parse("<a><remove><b/></remove><c><remove/><d/></c></a>")
+            .unwrap();
+        let keep = |el: &Element| el.name != "remove";
+        let pruned = tree.prune(&keep).unwrap();
+        assert_eq!(to_xml(&pruned), "<a><c><d/></c></a>");
+
+        let (_, root) = element().parse("<remove><a/></remove>").unwrap();
+        assert_eq!(root.prune(&keep), None);
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let mut events = Vec::new();
+        assert_eq!(
+            parse_events("<a><b/>text</a>", |event| events.push(event)),
+            Ok(())
+        );
+        assert_eq!(
+            events,
+            vec![
+                Event::Open {
+                    name: String::from("a"),
+                    attributes: vec![],
+                },
+                Event::Open {
+                    name: String::from("b"),
+                    attributes: vec![],
+                },
+                Event::Close {
+                    name: String::from("b"),
+                },
+                Event::Text(String::from("text")),
+                Event::Close {
+                    name: String::from("a"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_events_mismatched() {
+        assert_eq!(
+            parse_events("<a><b></a>", |_| {}),
+            Err(String::from("parse error at byte 6: unexpected \"</a>\""))
+        );
+        assert_eq!(
+            parse_events("<a/><b/>", |_| {}),
+            Err(String::from("parse error at byte 4: unexpected \"<b/>\""))
+        );
+    }
+
+    #[test]
+    fn test_collapse_whitespace() {
+        assert_eq!(collapse_whitespace("  hello   world \n"), "hello world");
+        assert_eq!(collapse_whitespace("   \n  "), "");
+    }
+
+    #[test]
+    fn test_trim_text() {
+        let doc = "<a>\n   <b>  hello   world \n</b>\n</a>";
+        let texts = |config: &ParserConfig| {
+            let mut texts = Vec::new();
+            parse_events_with(doc, config, |event| {
+                if let Event::Text(text) = event {
+                    texts.push(text);
+                }
+            })
+            .unwrap();
+            texts
+        };
+        assert_eq!(
+            texts(&ParserConfig {
+                trim_text: true,
+                ..ParserConfig::default()
+            }),
+            vec![String::from("hello world")]
+        );
+        assert_eq!(
+            texts(&ParserConfig::default()),
+            vec!["\n   ", "  hello   world \n", "\n"]
+        );
+    }
+
+    #[test]
+    fn test_match_any_literal() {
+        let options = ["<=", "<", ">"];
+        let parser = match_any_literal(&options);
+        assert_eq!(parser.parse("<= 1"), Ok((" 1", "<=")));
+        assert_eq!(parser.parse("< 1"), Ok((" 1", "<")));
+        assert_eq!(parser.parse("> 1"), Ok((" 1", ">")));
+        assert_eq!(parser.parse("= 1"), Err("= 1"));
+
+        let misordered = ["<", "<="];
+        assert_eq!(match_any_literal(&misordered).parse("<="), Ok(("=", "<")));
+    }
+
+    #[test]
+    fn test_scan() {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        enum Quote {
+            Start,
+            Body,
+            Escape,
+            End,
+        }
+
+        let string = scan(Quote::Start, |state, c| match (*state, c) {
+            (Quote::Start, '"') => {
+                *state = Quote::Body;
+                Some(None)
+            }
+            (Quote::Body, '\\') => {
+                *state = Quote::Escape;
+                Some(None)
+            }
+            (Quote::Body, '"') => {
+                *state = Quote::End;
+                Some(None)
+            }
+            (Quote::Body, c) => Some(Some(c)),
+            (Quote::Escape, 'n') => {
+                *state = Quote::Body;
+                Some(Some('\n'))
+            }
+            (Quote::Escape, c) => {
+                *state = Quote::Body;
+                Some(Some(c))
+            }
+            _ => None,
+        });
+
+        let (rest, (state, chars)) = string.parse(r#""a\"b\nc" rest"#).unwrap();
+        assert_eq!(rest, " rest");
+        assert_eq!(state, Quote::End);
+        assert_eq!(chars.into_iter().flatten().collect::<String>(), "a\"b\nc");
+
+        let (rest, (state, _)) = string.parse("\"open").unwrap();
+        assert_eq!((rest, state), ("", Quote::Body));
+    }
+
+    #[test]
+    fn test_escaped_string() {
+        assert_eq!(
+            escaped_string().parse(r#""a\nb""#),
+            Ok(("", String::from("a\nb")))
+        );
+        assert_eq!(
+            escaped_string().parse(r#""\u0041" rest"#),
+            Ok((" rest", String::from("A")))
+        );
+        assert_eq!(
+            escaped_string().parse(r#""\"\\\t""#),
+            Ok(("", String::from("\"\\\t")))
+        );
+        assert_eq!(escaped_string().parse(r#""\q""#), Err(r#"\q""#));
+        assert_eq!(escaped_string().parse(r#""\u00G1""#), Err(r#"\u00G1""#));
+        assert_eq!(escaped_string().parse(r#""open"#), Err(""));
+    }
+
+    #[test]
+    fn test_position() {
+        let doc = "abc def";
+        let parser = pair(left(match_ident, match_literal(" ")), position(doc));
+        assert_eq!(parser.parse(doc), Ok(("def", (String::from("abc"), 4))));
+        assert_eq!(position(doc).parse(doc), Ok((doc, 0)));
+
+        let doc = "\u{1F601}x";
+        assert_eq!(right(any_char, position(doc)).parse(doc), Ok(("x", 4)));
+    }
+
+    #[test]
+    fn test_attempt() {
+        let ab_cd = || pair(match_literal("ab"), match_literal("cd"));
+        assert_eq!(ab_cd().parse("abce"), Err("ce"));
+        assert_eq!(attempt(ab_cd()).parse("abce"), Err("abce"));
+
+        let parser = either(map(attempt(ab_cd()), |_| "abcd"), match_literal("abce"));
+        assert_eq!(parser.parse("abce"), Ok(("", "abce")));
+        assert_eq!(parser.parse("abcd"), Ok(("", "abcd")));
+    }
+
+    #[test]
+    fn test_xml_name_chars() {
+        assert!(is_xml_name_start(':'));
+        assert!(is_xml_name_start('\u{6F22}'));
+        assert!(!is_xml_name_start('-'));
+        assert!(!is_xml_name_start('\u{301}'));
+        assert!(is_xml_name_char('\u{301}'));
+        assert!(is_xml_name_char('\u{B7}'));
+        assert!(!is_xml_name_char(' '));
+    }
+
+    #[test]
+    fn test_xml_ident() {
+        let combining = "cafe\u{301}-bar>";
+        assert_eq!(
+            match_ident(combining),
+            Ok(("\u{301}-bar>", String::from("cafe")))
+        );
+        assert_eq!(
+            xml_ident().parse(combining),
+            Ok((">", String::from("cafe\u{301}-bar")))
+        );
+
+        let cjk = "\u{6F22}\u{5B57}2 x";
+        assert_eq!(
+            match_ident(cjk),
+            Ok(("2 x", String::from("\u{6F22}\u{5B57}")))
+        );
+        assert_eq!(
+            xml_ident().parse(cjk),
+            Ok((" x", String::from("\u{6F22}\u{5B57}2")))
+        );
+
+        assert_eq!(xml_ident().parse("1abc"), Err("1abc"));
+    }
+
+    #[test]
+    fn test_repeat_until_eof() {
+        let number = whitespace_wrap(map(one_or_more(char_range('0', '9')), |digits| {
+            digits
+                .into_iter()
+                .collect::<String>()
+                .parse::<u32>()
+                .unwrap()
+        }));
+        let numbers = repeat_until_eof(number);
+        assert_eq!(numbers.parse("1 2 3"), Ok(("", vec![1, 2, 3])));
+        assert_eq!(numbers.parse(""), Ok(("", vec![])));
+        assert_eq!(numbers.parse("1 2 x"), Err("x"));
+    }
+
+    #[test]
+    fn test_normalize_attr_whitespace() {
+        let config = ParserConfig {
+            normalize_attr_whitespace: true,
+            ..ParserConfig::default()
+        };
+        let attributes = |config: &ParserConfig| {
+            parse_str_with("<a id=\"a\tb\" x=\"1\r\n2\"/>", config).map(|el| el.attributes)
+        };
+        assert_eq!(
+            attributes(&config),
+            Ok(vec![
+                (String::from("id"), String::from("a b")),
+                (String::from("x"), String::from("1 2")),
+            ])
+        );
+        assert_eq!(
+            attributes(&ParserConfig::default()),
+            Ok(vec![
+                (String::from("id"), String::from("a\tb")),
+                (String::from("x"), String::from("1\r\n2")),
+            ])
+        );
+
+        let mut events = Vec::new();
+        assert_eq!(
+            parse_events_with("<a x=\"1\r\n\t2\"/>", &config, |event| events.push(event)),
+            Ok(())
+        );
+        assert_eq!(
+            events[0],
+            Event::Open {
+                name: String::from("a"),
+                attributes: vec![(String::from("x"), String::from("1  2"))],
+            }
+        );
+    }
+
+    #[test]
+    fn test_max_attr_len() {
+        let config = ParserConfig {
+            max_attr_name_len: Some(5),
+            ..ParserConfig::default()
+        };
+        assert_eq!(
+            parse_str_with(r#"<a><b longname="1"/></a>"#, &config),
+            Err(String::from(
+                "attribute name \"longname\" exceeds maximum length"
+            ))
+        );
+        assert!(parse_str_with(r#"<a><b short="1"/></a>"#, &config).is_ok());
+        // With std the parse stops at the oversized attribute, before the
+        // rest of the document is read.
+        #[cfg(feature = "std")]
+        assert_eq!(
+            parse_str_with(r#"<a longname="1"><unclosed>"#, &config),
+            Err(String::from(
+                "attribute name \"longname\" exceeds maximum length"
+            ))
+        );
+        assert!(parse_events_with(r#"<a longname="1"/>"#, &config, |_| {}).is_err());
+
+        let config = ParserConfig {
+            max_attr_value_len: Some(3),
+            ..ParserConfig::default()
+        };
+        assert_eq!(
+            parse_str_with(r#"<a x="1234"/>"#, &config),
+            Err(String::from(
+                "value of attribute \"x\" exceeds maximum length"
+            ))
+        );
+        assert!(parse_str_with(r#"<a x="123"/>"#, &config).is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_attr_limits_restored_on_panic() {
+        let config = ParserConfig {
+            max_attr_name_len: Some(1),
+            ..ParserConfig::default()
+        };
+        let panicked = std::panic::catch_unwind(|| with_attr_limits(&config, || panic!("inner")));
+        assert!(panicked.is_err());
+        // The limits from the panicking call must not leak into later parses.
+        assert!(parse_str(r#"<a long="1"/>"#).is_ok());
+    }
+
+    #[test]
+    fn test_max_input_len() {
+        let config = ParserConfig {
+            max_input_len: Some(10),
+            ..ParserConfig::default()
+        };
+        assert_eq!(
+            parse_str_with("<abcdefgh/>", &config),
+            Err(String::from("input exceeds maximum length"))
+        );
+        assert_eq!(
+            parse_str_with("<ab/>", &config).map(|el| el.name),
+            Ok(String::from("ab"))
+        );
+        assert_eq!(
+            parse_events_with("<abcdefgh/>", &config, |_| {}),
+            Err(String::from("input exceeds maximum length"))
+        );
+        assert!(parse_str_with("<abcdefgh/>", &ParserConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_xml_decl() {
+        assert_eq!(
+            xml_decl().parse("<?xml version=\"1.0\" encoding=\"UTF-8\"?><a/>"),
+            Ok((
+                "<a/>",
+                vec![
+                    (String::from("version"), String::from("1.0")),
+                    (String::from("encoding"), String::from("UTF-8"))
+                ]
+            ))
+        );
+        assert_eq!(
+            xml_decl().parse("<?xml-stylesheet href=\"a.xsl\"?>"),
+            Err("-stylesheet href=\"a.xsl\"?>")
+        );
+    }
+
+    #[test]
+    fn test_processing_instruction() {
+        assert_eq!(
+            processing_instruction().parse("<?xml-stylesheet href=\"a.xsl\"?><a/>"),
+            Ok((
+                "<a/>",
+                (
+                    String::from("xml-stylesheet"),
+                    String::from("href=\"a.xsl\"")
+                )
+            ))
+        );
+        assert_eq!(
+            processing_instruction().parse("<?target?>"),
+            Ok(("", (String::from("target"), String::new())))
+        );
+        assert_eq!(processing_instruction().parse("<?open"), Err(""));
+        assert_eq!(
+            processing_instruction().parse("<?xml version=\"1.0\"?>"),
+            Err("<?xml version=\"1.0\"?>")
+        );
+        assert_eq!(
+            processing_instruction().parse("<?XmL x?>"),
+            Err("<?XmL x?>")
+        );
+        assert!(parse_str("<?xml version=\"1.0\"?><?xml version=\"1.0\"?><a/>").is_err());
+        assert!(parse_str("<?xml version=\"1.0\"?><?xml-stylesheet x?><a/>").is_ok());
+    }
+
+    #[test]
+    fn test_parse_str_with_prolog() {
+        let doc = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                   <?xml-stylesheet href=\"a.xsl\"?>\n\
+                   <root><a/></root>";
+        assert_eq!(
+            parse_str(doc).map(|el| to_xml(&el)),
+            Ok(String::from("<root><a/></root>"))
+        );
+
+        let mut events = 0;
+        assert_eq!(parse_events(doc, |_| events += 1), Ok(()));
+        assert_eq!(events, 4);
+        assert_eq!(parse_events("  <a/>  ", |_| {}), Ok(()));
+    }
+
+    #[test]
+    fn test_doctype() {
+        assert_eq!(
+            doctype().parse("<!DOCTYPE html><html/>"),
+            Ok(("<html/>", String::from("<!DOCTYPE html>")))
+        );
+        let subset = "<!DOCTYPE note [<!ELEMENT note (#PCDATA)> <!ENTITY gt \">\">]>";
+        let doc = format!("{}<note/>", subset);
+        assert_eq!(doctype().parse(&doc), Ok(("<note/>", String::from(subset))));
+        assert_eq!(doctype().parse("<!DOCTYPE html"), Err(""));
+        assert_eq!(doctype().parse("<html/>"), Err("<html/>"));
+    }
+
+    #[test]
+    fn test_parse_str_with_doctype() {
+        assert_eq!(
+            parse_str("<!DOCTYPE html><html/>").map(|el| el.name),
+            Ok(String::from("html"))
+        );
+        assert_eq!(
+            parse_str("<?xml version=\"1.0\"?>\n<!DOCTYPE html>\n<?pi?>\n<html/>")
+                .map(|el| el.name),
+            Ok(String::from("html"))
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let built = Element::builder("top")
+            .attr("label", "Top")
+            .child(Element::builder("bottom").attr("id", "1").build())
+            .child(Element::builder("empty").build())
+            .build();
+        assert_eq!(
+            built,
+            Element {
+                name: String::from("top"),
+                attributes: vec![(String::from("label"), String::from("Top"))],
+                children: vec![
+                    Node::Element(Element {
+                        name: String::from("bottom"),
+                        attributes: vec![(String::from("id"), String::from("1"))],
+                        children: vec![],
+                    }),
+                    Node::Element(Element {
+                        name: String::from("empty"),
+                        attributes: vec![],
+                        children: vec![],
+                    }),
+                ],
+            }
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_hash() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let hash = |el: &Element| {
+            let mut hasher = DefaultHasher::new();
+            el.hash(&mut hasher);
+            hasher.finish()
+        };
+        let a = parse_str("<a x=\"1\" y=\"2\"><b/></a>").unwrap();
+        let reordered = parse_str("<a y=\"2\" x=\"1\"><b/></a>").unwrap();
+        assert_eq!(hash(&a), hash(&a.clone()));
+        assert_eq!(hash(&a), hash(&reordered));
+        assert_ne!(hash(&a), hash(&parse_str("<a x=\"1\" y=\"2\"/>").unwrap()));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_intern() {
+        let root = parse_str("<root><a id=\"1\"><b/></a><c/><a id=\"1\"><b/></a></root>").unwrap();
+        let children: Vec<Element> = root.child_elements().cloned().collect();
+        let interned = intern(&children);
+        assert_eq!(interned.len(), 3);
+        assert!(std::rc::Rc::ptr_eq(&interned[0], &interned[2]));
+        assert!(!std::rc::Rc::ptr_eq(&interned[0], &interned[1]));
+        assert_eq!(*interned[2], children[2]);
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        assert_eq!(
+            parse_partial("<a/>TRAILER"),
+            Ok((Element::builder("a").build(), "TRAILER"))
+        );
+        assert_eq!(
+            parse_partial("  <a><b/></a> \nnext"),
+            Ok((
+                Element::builder("a")
+                    .child(Element::builder("b").build())
+                    .build(),
+                " \nnext"
+            ))
+        );
+        assert_eq!(
+            parse_partial("TRAILER"),
+            Err(String::from(
+                "parse error at byte 0: unexpected \"TRAILER\""
+            ))
+        );
+    }
+
+    #[test]
+    fn test_validate_unique_attributes() {
+        let config = ParserConfig {
+            unique_attributes: true,
+            ..ParserConfig::default()
+        };
+        assert_eq!(
+            parse_str_with("<a x=\"1\" x=\"2\"/>", &config),
+            Err(String::from("duplicate attribute \"x\" on <a>"))
+        );
+        assert_eq!(
+            parse_str_with("<a><b y=\"1\" y=\"1\"/></a>", &config),
+            Err(String::from("duplicate attribute \"y\" on <b>"))
+        );
+        assert!(parse_str_with("<a x=\"1\" y=\"2\"/>", &config).is_ok());
+        assert!(parse_str("<a x=\"1\" x=\"2\"/>").is_ok());
+
+        assert_eq!(
+            parse_events_with("<a><b y=\"1\" y=\"1\"/></a>", &config, |_| {}),
+            Err(String::from("duplicate attribute \"y\" on <b>"))
+        );
+        assert!(parse_events_with("<a x=\"1\" y=\"2\"/>", &config, |_| {}).is_ok());
+        assert!(parse_events("<a x=\"1\" x=\"2\"/>", |_| {}).is_ok());
+    }
+
+    #[test]
+    fn test_literal_bytes() {
+        let parser = match_literal_bytes(b"GET ");
+        assert_eq!(
+            parser(b"GET /index.html"),
+            Ok((&b"/index.html"[..], &b"GET "[..]))
+        );
+        assert_eq!(parser(b"POST /"), Err(&b"POST /"[..]));
+        assert_eq!(parser(b"GE"), Err(&b"GE"[..]));
+    }
+
+    #[test]
+    fn test_take_until() {
+        assert_eq!(take_until("-->").parse("a b-->c"), Ok(("-->c", "a b")));
+        assert_eq!(take_until("-->").parse("-->"), Ok(("-->", "")));
+        assert_eq!(take_until("-->").parse("abc"), Err("abc"));
+        assert_eq!(
+            take_until("\u{1F601}").parse("\u{E9}t\u{E9}\u{1F601}x"),
+            Ok(("\u{1F601}x", "\u{E9}t\u{E9}"))
+        );
+    }
+
+    // The plain char-by-char search, as a reference for the others.
+    fn find_scalar(haystack: &str, needle: &str) -> Option<usize> {
+        haystack
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(core::iter::once(haystack.len()))
+            .find(|&i| haystack[i..].starts_with(needle))
+    }
+
+    #[test]
+    fn test_find_fast_matches_scalar() {
+        let long = "\u{E9}abc ".repeat(10) + "-->" + &"x".repeat(9);
+        let haystacks = [
+            "",
+            "abc",
+            "\u{E9}\u{E9}>",
+            "\u{1F601}\u{1F601}<\u{1F601}",
+            "\u{E0}\u{A9}",
+            "x\u{1F600}\u{1F601}",
+            "--\u{E9}--->",
+            &long,
+        ];
+        let needles = ["", ">", "<", "-->", "\u{1F601}", "\u{A9}", "\u{E9}>", "zz"];
+        for haystack in &haystacks {
+            for needle in &needles {
+                let expected = find_scalar(haystack, needle);
+                assert_eq!(
+                    find_fast(haystack, needle),
+                    expected,
+                    "{:?} in {:?}",
+                    needle,
+                    haystack
+                );
+                assert_eq!(haystack.find(needle), expected);
+            }
+        }
+        for len in 0..40 {
+            let bytes = "a".repeat(len) + "<";
+            assert_eq!(memchr(b'<', bytes.as_bytes()), Some(len));
+            assert_eq!(memchr(b'>', bytes.as_bytes()), None);
+        }
+    }
+
+    // cargo test --release -- --ignored bench_take_until --nocapture
+    #[cfg(feature = "std")]
+    #[test]
+    #[ignore]
+    fn bench_take_until() {
+        let mut input = "\u{E9}abc ".repeat(1024 * 1024 / 6);
+        input.push_str("-->");
+        let str_find = |haystack: &str, needle: &str| haystack.find(needle);
+        for (label, find) in [
+            ("scalar", find_scalar as fn(&str, &str) -> Option<usize>),
+            ("str::find", str_find),
+            ("memchr", find_fast),
+        ]
+        .iter()
+        {
+            let start = std::time::Instant::now();
+            for _ in 0..10 {
+                assert_eq!(find(&input, "-->"), Some(input.len() - 3));
+            }
+            println!("{}: {:?} per 1MB scan", label, start.elapsed() / 10);
+        }
+    }
+
+    #[test]
+    fn test_balanced() {
+        let parens = balanced('(', ')');
+        assert_eq!(parens.parse("(a(b)c)rest"), Ok(("rest", "a(b)c")));
+        assert_eq!(parens.parse("()"), Ok(("", "")));
+        assert_eq!(parens.parse("(a(b)"), Err("(a(b)"));
+        assert_eq!(parens.parse("a(b)"), Err("a(b)"));
+        let quotes = balanced('"', '"');
+        assert_eq!(quotes.parse(r#""ab"c""#), Ok((r#"c""#, "ab")));
+        assert_eq!(quotes.parse(r#""ab"#), Err(r#""ab"#));
+        assert_eq!(
+            balanced('\u{AB}', '\u{BB}').parse("\u{AB}x\u{AB}y\u{BB}\u{BB}!"),
+            Ok(("!", "x\u{AB}y\u{BB}"))
+        );
+    }
+
+    #[test]
+    fn test_spanned() {
+        let doc = "<abc>";
+        assert_eq!(
+            right(match_literal("<"), spanned(doc, match_ident)).parse(doc),
+            Ok((">", (String::from("abc"), (1, 4))))
+        );
+    }
+
+    #[test]
+    fn test_map_with_span() {
+        #[derive(Debug, PartialEq)]
+        struct Ident {
+            name: String,
+            start: usize,
+            end: usize,
+        }
+
+        let doc = "foo  bar";
+        let ident = map_with_span(doc, match_ident, |name, (start, end)| Ident {
+            name,
+            start,
+            end,
+        });
+        assert_eq!(
+            zero_or_more(whitespace_wrap(ident)).parse(doc),
+            Ok((
+                "",
+                vec![
+                    Ident {
+                        name: String::from("foo"),
+                        start: 0,
+                        end: 3,
+                    },
+                    Ident {
+                        name: String::from("bar"),
+                        start: 5,
+                        end: 8,
+                    },
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_complete() {
+        assert_eq!(match_ident.parse_complete("abc"), Ok(String::from("abc")));
+        assert_eq!(match_ident.parse_complete("abc!"), Err("!"));
+        assert_eq!(match_ident.parse_complete("!abc"), Err("!abc"));
+    }
+
+    #[cfg(all(feature = "std", debug_assertions))]
+    #[test]
+    #[should_panic(expected = "left recursion detected")]
+    fn test_left_recursion_guard() {
+        // list = list any_char | any_char
+        let grammar: Grammar<Vec<char>> = Grammar::new();
+        grammar.define(
+            "list",
+            either(
+                map(pair(grammar.rule("list"), any_char), |(mut items, c)| {
+                    items.push(c);
+                    items
+                }),
+                map(any_char, |c| vec![c]),
+            ),
+        );
+
+        let _ = grammar.rule("list").parse("abc");
+    }
+
+    #[cfg(all(feature = "std", debug_assertions))]
+    #[test]
+    fn test_recursion_guard_same_site() {
+        // Both rules come from the same `BoxedParser::new` call and the outer
+        // one enters the inner one without consuming input.
+        fn rule<'a, P: Parser<'a, String> + 'a>(parser: P) -> BoxedParser<'a, String> {
+            BoxedParser::new(parser)
+        }
+
+        let inner = rule(match_ident);
+        let outer = rule(move |input| inner.parse(input));
+        assert_eq!(outer.parse("abc"), Ok(("", String::from("abc"))));
+    }
+
+    #[test]
+    fn test_recursion_guard_allows_nesting() {
+        let doc = "<a><a><a/></a></a>";
+        assert_eq!(element().parse(doc).map(|(_, el)| el.depth()), Ok(3));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_ws_skipping() {
+        let doc = "< a id = \"1\" >< b / ></ a >";
+        assert!(parse_str(doc).is_err());
+        assert_eq!(
+            with_ws_skipping(|| parse_str(doc)),
+            Ok(Element::builder("a")
+                .attr("id", "1")
+                .child(Element::builder("b").build())
+                .build())
+        );
+        assert_eq!(
+            with_ws_skipping(|| parse_str("< a / >")),
+            Ok(Element::builder("a").build())
+        );
+        assert!(parse_str("< a / >").is_err());
+        assert!(parse_str("<a/>").is_ok());
+    }
+
+    #[test]
+    fn test_many_count() {
+        assert_eq!(many0_count(match_literal(" ")).parse("    x"), Ok(("x", 4)));
+        assert_eq!(many0_count(match_literal(" ")).parse("x"), Ok(("x", 0)));
+        assert_eq!(many1_count(match_literal(" ")).parse("  x"), Ok(("x", 2)));
+        assert_eq!(many1_count(match_literal(" ")).parse("x"), Err("x"));
+    }
+
+    #[test]
+    fn test_indentation() {
+        assert_eq!(indentation().parse("    x"), Ok(("    x", 4)));
+        assert_eq!(indentation().parse("x"), Ok(("x", 0)));
+        assert_eq!(
+            indented_by(2, match_ident).parse("  ab\n"),
+            Ok(("\n", String::from("ab")))
+        );
+        assert_eq!(indented_by(2, match_ident).parse("   ab"), Err("   ab"));
+        assert_eq!(indented_by(2, match_ident).parse(" ab"), Err(" ab"));
+    }
+
+    #[test]
+    fn test_indented_block() {
+        fn block<'a>(level: usize) -> BoxedParser<'a, Element> {
+            BoxedParser::new(and_then(
+                indented_by(level, left(match_ident, optional(match_literal("\n")))),
+                move |name| {
+                    map(zero_or_more(block(level + 2)), move |children| Element {
+                        name: name.clone(),
+                        attributes: vec![],
+                        children: children.into_iter().map(Node::Element).collect(),
+                    })
+                },
+            ))
+        }
+
+        let outline = "root\n  left\n    leaf\n  right\n";
+        assert_eq!(
+            block(0).parse(outline),
+            Ok((
+                "",
+                Element::builder("root")
+                    .child(
+                        Element::builder("left")
+                            .child(Element::builder("leaf").build())
+                            .build()
+                    )
+                    .child(Element::builder("right").build())
+                    .build()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_equals_unordered() {
+        let a = parse_str("<a x=\"1\" y=\"2\"><b z=\"3\"/></a>").unwrap();
+        let reordered = parse_str("<a y=\"2\" x=\"1\"><b z=\"3\"/></a>").unwrap();
+        assert!(a != reordered);
+        assert!(a.equals_unordered(&reordered));
+        assert!(!a.equals_unordered(&parse_str("<a x=\"1\" y=\"2\"><b/></a>").unwrap()));
+    }
+
+    // Seeded xorshift so the round-trip property runs without extra crates.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn pick(&mut self, chars: &[char]) -> char {
+            chars[self.below(chars.len())]
+        }
+
+        fn string(&mut self, first: &[char], rest: &[char], max_len: usize) -> String {
+            let mut s = String::new();
+            s.push(self.pick(first));
+            for _ in 0..self.below(max_len) {
+                s.push(self.pick(rest));
+            }
+            s
+        }
+
+        fn element(&mut self, depth: usize) -> Element {
+            let letters = ['a', 'b', 'z', 'X', '\u{E9}', '\u{6F22}'];
+            let name_start = ['a', 'X', '_', '\u{6F22}'];
+            let name_chars = ['a', 'Q', '1', '-', '.', ':', '\u{E9}'];
+            let value_chars = [
+                'a',
+                ' ',
+                '1',
+                '\'',
+                '=',
+                '/',
+                '&',
+                '<',
+                '>',
+                '"',
+                '\u{1F601}',
+            ];
+            let name = self.string(&name_start, &name_chars, 6);
+            let attributes = (0..self.below(4))
+                .map(|_| {
+                    (
+                        self.string(&letters, &['a', '-', 'Z'], 4),
+                        self.string(&value_chars, &value_chars, 6),
                     )
+                })
+                .collect();
+            let children = if depth == 0 {
+                vec![]
+            } else {
+                (0..self.below(4))
+                    .map(|_| Node::Element(self.element(depth - 1)))
+                    .collect()
+            };
+            Element {
+                name,
+                attributes,
+                children,
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_xml_round_trip() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        for _ in 0..256 {
+            let element = rng.element(3);
+            let xml = to_xml(&element);
+            let parsed = parse_str(&xml).unwrap_or_else(|err| panic!("{}: {}", xml, err));
+            assert!(parsed.equals_unordered(&element), "{}", xml);
+        }
+    }
+
+    #[test]
+    fn test_mixed_content() {
+        assert_eq!(
+            parse_str("<p>\n  Hello <b>big</b>\n  <br/>\n</p>"),
+            Ok(Element::builder("p")
+                .text("\n  Hello ")
+                .child(Element::builder("b").text("big").build())
+                .child(Element::builder("br").build())
+                .build())
+        );
+
+        let config = ParserConfig {
+            trim_text: true,
+            ..ParserConfig::default()
+        };
+        assert_eq!(
+            parse_str_with("<p>\n  Hello   world\n</p>", &config),
+            Ok(Element::builder("p").text("Hello world").build())
+        );
+    }
+
+    #[test]
+    fn test_text_content() {
+        let p = parse_str("<p>Hello <b>big <i>wide</i></b> world<br/>!</p>").unwrap();
+        assert_eq!(p.text_content(), "Hello big wide world!");
+        assert_eq!(Element::builder("br").build().text_content(), "");
+    }
+
+    #[test]
+    fn test_parse_with_recovery() {
+        let doc = "<a><b>ok</b><c x=1/><d/></a>";
+        let (tree, errors) = parse_with_recovery(doc);
+        assert_eq!(
+            tree,
+            Element::builder("a")
+                .child(Element::builder("b").text("ok").build())
+                .child(Element::builder("#error").text("<c x=1/>").build())
+                .child(Element::builder("d").build())
+                .build()
+        );
+        assert_eq!(
+            errors,
+            vec![ParseError {
+                offset: 12,
+                message: String::from("parse error at byte 12: unexpected \"<c x=1/><d\""),
+                context: vec![],
+            }]
+        );
+
+        let (tree, errors) = parse_with_recovery("<a><b/>");
+        assert_eq!(tree.to_string(), "<a><b/></a>");
+        assert_eq!(errors[0].to_string(), "unexpected end of input");
+
+        let (tree, errors) = parse_with_recovery("garbage");
+        assert_eq!(tree.name, "#error");
+        assert_eq!(errors.len(), 1);
+
+        assert_eq!(parse_with_recovery("<a><b/></a>").1, vec![]);
+    }
+
+    #[test]
+    fn test_match_ident_ref() {
+        let input = "one-ident rest";
+        let (rest, ident) = match_ident_ref(input).unwrap();
+        assert_eq!(ident, "one-ident");
+        assert_eq!(rest, " rest");
+        assert_eq!(ident.as_ptr(), input.as_ptr());
+        assert_eq!(
+            match_ident_ref("héllo!"),
+            match_ident("héllo!").map(|(rest, _)| (rest, "héllo"))
+        );
+        assert_eq!(match_ident_ref("1abc"), Err("1abc"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parse_str_with_warnings() {
+        let (element, warnings) = parse_str_with_warnings("<A/>").unwrap();
+        assert_eq!(element, Element::builder("A").build());
+        assert_eq!(warnings, vec!["uppercase tag name <A>"]);
+
+        let (_, warnings) = parse_str_with_warnings(r#"<B x=""><c/></B>"#).unwrap();
+        assert_eq!(
+            warnings,
+            vec!["uppercase tag name <B>", "empty attribute value for \"x\""]
+        );
+
+        assert_eq!(
+            parse_str_with_warnings("<a/>").unwrap().1,
+            Vec::<String>::new()
+        );
+        assert!(parse_str_with_warnings("<A>").is_err());
+        assert_eq!(parse_str("<A/>"), Ok(Element::builder("A").build()));
+    }
+
+    #[test]
+    fn test_seq() {
+        let open_tag = seq!(
+            _ = token("<"),
+            name = tag_name(),
+            attributes = attributes(),
+            _ = token(">") => Element {
+                name,
+                attributes,
+                children: vec![],
+            }
+        );
+        assert_eq!(
+            open_tag.parse(r#"<div class="x">rest"#),
+            Ok(("rest", Element::builder("div").attr("class", "x").build()))
+        );
+        assert_eq!(open_tag.parse("<div/>"), Err("/>"));
+    }
+
+    #[test]
+    fn test_comment() {
+        assert_eq!(
+            comment().parse("<!-- a <b> -->rest"),
+            Ok(("rest", String::from(" a <b> ")))
+        );
+        assert_eq!(comment().parse("<!-- open"), Err(" open"));
+    }
+
+    #[test]
+    fn test_alt() {
+        let node = alt!(
+            map(single_element(), Node::Element)
+                | map(parent_element(), Node::Element)
+                | map(comment(), Node::Text)
+        );
+        assert_eq!(
+            node.parse("<a/>rest"),
+            Ok(("rest", Node::Element(Element::builder("a").build())))
+        );
+        assert_eq!(
+            node.parse("<a><b/></a>rest"),
+            Ok((
+                "rest",
+                Node::Element(
+                    Element::builder("a")
+                        .child(Element::builder("b").build())
+                        .build()
+                )
+            ))
+        );
+        assert_eq!(
+            node.parse("<!--c-->rest"),
+            Ok(("rest", Node::Text(String::from("c"))))
+        );
+        assert_eq!(node.parse("<a><b></a>"), Err("<b></a>"));
+
+        let trailing = alt!(match_literal("x") | (map(match_literal("yy"), |y| y)) |);
+        assert_eq!(trailing.parse("yyz"), Ok(("z", "yy")));
+        assert_eq!(trailing.parse("z"), Err("z"));
+    }
+
+    #[test]
+    fn test_match_literal_ws() {
+        assert_eq!(
+            match_literal_ws("=>").parse("  =>  rest"),
+            Ok(("rest", "=>"))
+        );
+        assert_eq!(match_literal_ws("=>").parse("=>rest"), Ok(("rest", "=>")));
+        assert_eq!(match_literal_ws("=>").parse("  = >"), Err("= >"));
+    }
+
+    #[test]
+    fn test_line() {
+        assert_eq!(line().parse("a\nb"), Ok(("b", "a")));
+        assert_eq!(line().parse("\nb"), Ok(("b", "")));
+        assert_eq!(line().parse("b"), Ok(("", "b")));
+        assert_eq!(line().parse(""), Err(""));
+    }
+
+    #[test]
+    fn test_lines() {
+        let letters = lines(any_char);
+        assert_eq!(letters.parse("a\nb\nc"), Ok(("", vec!['a', 'b', 'c'])));
+        assert_eq!(letters.parse("a\nb\nc\n"), Ok(("", vec!['a', 'b', 'c'])));
+        assert_eq!(letters.parse(""), Ok(("", vec![])));
+        assert_eq!(letters.parse("a\nbc\nd"), Err("c"));
+        assert_eq!(
+            lines(optional(any_char)).parse("x\n\ny"),
+            Ok(("", vec![Some('x'), None, Some('y')]))
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        let a = parse_str(r#"<a y="2" x="1"><b>  hello   world </b><c/></a>"#).unwrap();
+        let b = Element::builder("a")
+            .attr("x", "1")
+            .attr("y", "2")
+            .child(Element::builder("b").text("hello ").text(" world").build())
+            .text("  ")
+            .child(Element::builder("c").build())
+            .build();
+        assert_ne!(a, b);
+        assert_eq!(normalize(a.clone()), normalize(b));
+        assert_eq!(
+            normalize(a.clone()).to_string(),
+            r#"<a x="1" y="2"><b>hello world</b><c/></a>"#
+        );
+        assert_eq!(normalize(normalize(a.clone())), normalize(a));
+    }
+
+    #[test]
+    fn test_parse_tokens() {
+        let word = || left(match_ident, space0());
+        assert_eq!(
+            parse_tokens(word(), "foo bar baz"),
+            vec![
+                (String::from("foo"), 0, 4),
+                (String::from("bar"), 4, 8),
+                (String::from("baz"), 8, 11),
+            ]
+        );
+        assert_eq!(
+            parse_tokens(word(), "foo 1"),
+            vec![(String::from("foo"), 0, 4)]
+        );
+        assert_eq!(parse_tokens(space0(), "x"), vec![]);
+    }
+
+    #[test]
+    fn test_quoted_string_any() {
+        assert_eq!(
+            quoted_string_any().parse("'hello'"),
+            Ok(("", String::from("hello")))
+        );
+        assert_eq!(
+            quoted_string_any().parse("\"hello\""),
+            Ok(("", String::from("hello")))
+        );
+        assert_eq!(
+            quoted_string_any().parse("'say \"hi\"'"),
+            Ok(("", String::from("say \"hi\"")))
+        );
+        assert_eq!(quoted_string_any().parse("'hello\""), Err(""));
+        assert_eq!(
+            parse_str("<a id='1' b=\"it's\"/>"),
+            Ok(Element::builder("a")
+                .attr("id", "1")
+                .attr("b", "it's")
+                .build())
+        );
+    }
+
+    #[test]
+    fn test_collect_string() {
+        let word = collect_string(one_or_more(char_range('a', 'z')));
+        assert_eq!(word.parse("abc123"), Ok(("123", String::from("abc"))));
+        assert_eq!(word.parse("123"), Err("123"));
+        assert_eq!(
+            collect_string(zero_or_more(char_range('a', 'z'))).parse("123"),
+            Ok(("123", String::new()))
+        );
+    }
+
+    #[test]
+    fn test_locate() {
+        let doc = "<a>\n  <b x=1/>\n</a>";
+        assert_eq!(locate(doc, doc), (1, 1));
+        assert_eq!(locate(doc, &doc[11..]), (2, 8));
+        assert_eq!(locate(doc, ""), (3, 5));
+        assert_eq!(locate("é<", "<"), (1, 2));
+    }
+
+    #[test]
+    fn test_error_snippet() {
+        let doc = "<a>\n  <b x=1/>\n</a>";
+        assert_eq!(
+            error_snippet(doc, &doc[11..], 80),
+            "2:8\n  <b x=1/>\n       ^"
+        );
+        assert_eq!(error_snippet(doc, &doc[11..], 3), "2:8\n x=1/>\n   ^");
+        assert_eq!(error_snippet("<a>", "", 5), "1:4\n<a>\n   ^");
+    }
+
+    #[test]
+    fn test_sep_by_trailing() {
+        let digits = sep_by_trailing(char_range('0', '9'), match_literal(","));
+        assert_eq!(digits.parse("1,2,3"), Ok(("", vec!['1', '2', '3'])));
+        assert_eq!(digits.parse("1,2,3,"), Ok(("", vec!['1', '2', '3'])));
+        assert_eq!(digits.parse("1,2,3]"), Ok(("]", vec!['1', '2', '3'])));
+        assert_eq!(digits.parse("1,,2"), Err(",2"));
+        assert_eq!(digits.parse(""), Ok(("", vec![])));
+        assert_eq!(digits.parse(","), Ok((",", vec![])));
+    }
+
+    #[test]
+    fn test_choice_by() {
+        let keyword = choice_by(vec![
+            (1, BoxedParser::new(map(match_ident, |_| "ident"))),
+            (5, BoxedParser::new(map(match_literal("if"), |_| "keyword"))),
+            (5, BoxedParser::new(map(match_literal("i"), |_| "letter"))),
+        ]);
+        assert_eq!(keyword.parse("if x"), Ok((" x", "keyword")));
+        assert_eq!(keyword.parse("iffy"), Ok(("fy", "keyword")));
+        assert_eq!(keyword.parse("abc"), Ok(("", "ident")));
+        assert_eq!(keyword.parse("1"), Err("1"));
+    }
+
+    #[test]
+    fn test_tagged_choice() {
+        let element = tagged_choice(vec![
+            ("single", BoxedParser::new(single_element())),
+            ("parent", BoxedParser::new(parent_element())),
+        ]);
+        assert_eq!(
+            element.parse("<a><b/></a>"),
+            Ok((
+                "",
+                (
+                    "parent",
+                    Element {
+                        name: String::from("a"),
+                        attributes: vec![],
+                        children: vec![Node::Element(Element {
+                            name: String::from("b"),
+                            attributes: vec![],
+                            children: vec![],
+                        })],
+                    }
+                )
+            ))
+        );
+        assert_eq!(
+            element.parse("<a/>").map(|(rest, (tag, _))| (rest, tag)),
+            Ok(("", "single"))
+        );
+        assert!(element.parse("a").is_err());
+    }
+
+    #[test]
+    fn test_interpolated_value() {
+        assert_eq!(
+            interpolated_value().parse(r#""a ${x} b ${y}" rest"#),
+            Ok((
+                " rest",
+                vec![
+                    ValuePart::Literal(String::from("a ")),
+                    ValuePart::Ref(String::from("x")),
+                    ValuePart::Literal(String::from(" b ")),
+                    ValuePart::Ref(String::from("y")),
+                ]
+            ))
+        );
+        assert_eq!(
+            interpolated_value().parse(r#""plain $ text""#),
+            Ok(("", vec![ValuePart::Literal(String::from("plain $ text"))]))
+        );
+        assert_eq!(interpolated_value().parse(r#""a ${x b""#), Err(r#"${x b""#));
+    }
+
+    #[test]
+    fn test_parse_to_option() {
+        assert_eq!(
+            parse_to_option(match_literal("<").parse("<a")),
+            Some(("a", "<"))
+        );
+        assert_eq!(parse_to_option(match_literal("<").parse("a")), None);
+    }
+
+    #[test]
+    fn test_or_none() {
+        let digit = or_none(|input: &str| {
+            let c = input.chars().next()?;
+            Some((&input[c.len_utf8()..], c.to_digit(10)?))
+        });
+        assert_eq!(digit.parse("7x"), Ok(("x", 7)));
+        assert_eq!(digit.parse("x7"), Err("x7"));
+        assert_eq!(digit.parse(""), Err(""));
+        assert_eq!(
+            or_none(|input| parse_to_option(match_ident(input))).parse("ab1"),
+            Ok(("1", String::from("ab")))
+        );
+    }
+
+    #[cfg(feature = "approx-graphemes")]
+    #[test]
+    fn test_any_grapheme() {
+        let flags = "\u{1F1FA}\u{1F1F8}\u{1F1EB}\u{1F1F7}";
+        assert_eq!(any_grapheme().parse(flags), Ok((&flags[8..], &flags[..8])));
+        assert_eq!(any_char(flags), Ok((&flags[4..], '\u{1F1FA}')));
+
+        let thumbs = "\u{1F44D}\u{1F3FD}!";
+        assert_eq!(any_grapheme().parse(thumbs), Ok(("!", &thumbs[..8])));
+        let family = "\u{1F469}\u{200D}\u{1F467}x";
+        assert_eq!(any_grapheme().parse(family), Ok(("x", &family[..11])));
+        assert_eq!(any_grapheme().parse("e\u{301}t"), Ok(("t", "e\u{301}")));
+        assert_eq!(any_grapheme().parse("\r\nx"), Ok(("x", "\r\n")));
+        assert_eq!(any_grapheme().parse("ab"), Ok(("b", "a")));
+        assert_eq!(any_grapheme().parse(""), Err(""));
+        // Known gap of the approximation: spacing marks are not joined.
+        assert_eq!(
+            any_grapheme().parse("\u{915}\u{93F}"),
+            Ok(("\u{93F}", "\u{915}"))
+        );
+    }
+
+    #[test]
+    fn test_junk() {
+        assert_eq!(junk().parse("  // hi\n /* x */ code"), Ok(("code", ())));
+        assert_eq!(junk().parse("code"), Ok(("code", ())));
+        assert_eq!(junk().parse("// only"), Ok(("", ())));
+        assert_eq!(junk().parse(" /* open"), Err("/* open"));
+        assert_eq!(junk().parse("/* a /* b */ c */x"), Ok(("c */x", ())));
+    }
+
+    #[test]
+    fn test_junk_with() {
+        let nested = CommentStyle {
+            nested_blocks: true,
+            ..CommentStyle::default()
+        };
+        assert_eq!(junk_with(nested).parse("/* a /* b */ c */x"), Ok(("x", ())));
+        assert_eq!(junk_with(nested).parse("/* a /* b */"), Err("/* a /* b */"));
+
+        let shell = CommentStyle {
+            line: Some("#"),
+            block: None,
+            nested_blocks: false,
+        };
+        assert_eq!(
+            junk_with(shell).parse("# hi\n  # there\nls"),
+            Ok(("ls", ()))
+        );
+        assert_eq!(junk_with(shell).parse("/* x */"), Ok(("/* x */", ())));
+    }
+
+    #[test]
+    fn test_with_context() {
+        let open_close = with_context(
+            right(match_literal("<"), left(tag_name(), match_literal(">"))),
+            |name: &String| BoxedParser::new(close_element(name.clone())),
+        );
+        assert_eq!(
+            open_close.parse("<foo></foo>rest"),
+            Ok(("rest", (String::from("foo"), String::from("foo"))))
+        );
+        assert_eq!(open_close.parse("<foo></bar>"), Err("</bar>"));
+    }
+
+    #[test]
+    fn test_byte() {
+        assert_eq!(any_byte("ab"), Ok(("b", b'a')));
+        assert_eq!(any_byte("\u{1F601}"), Err("\u{1F601}"));
+        assert_eq!(any_byte(""), Err(""));
+        assert_eq!(byte(b'\r').parse("\r\n"), Ok(("\n", b'\r')));
+        assert_eq!(byte(b'\r').parse("\n"), Err("\n"));
+        assert_eq!(byte(0xF0).parse("\u{1F601}"), Err("\u{1F601}"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parse_stats() {
+        let doc = "<a><b><c/></b><d/></a>";
+        let (element, stats) = parse_stats(doc).unwrap();
+        assert_eq!(element, parse_str(doc).unwrap());
+        assert_eq!(stats.bytes, doc.len());
+        assert_eq!(stats.nodes, 4);
+        assert_eq!(stats.max_depth, 3);
+        assert!(stats.elapsed >= core::time::Duration::ZERO);
+        assert!(parse_stats("<a>").is_err());
+    }
+
+    #[test]
+    fn test_indent_stack() {
+        fn outline(input: &str) -> Result<Element, &str> {
+            let stack = IndentStack::new();
+            let mut open = vec![Element::builder("outline").build()];
+            let mut rest = input;
+            while !rest.is_empty() {
+                if indent(&stack).parse(rest).is_ok() {
+                    match open.last_mut().and_then(|el| el.children.pop()) {
+                        Some(Node::Element(parent)) => open.push(parent),
+                        _ => return Err(rest),
+                    }
+                } else if let Ok((_, closed)) = dedent(&stack).parse(rest) {
+                    for _ in 0..closed {
+                        let done = open.pop().unwrap();
+                        open.last_mut().unwrap().children.push(Node::Element(done));
+                    }
+                }
+                let (next, name) =
+                    samedent(&stack, left(match_ident, optional(match_literal("\n"))))
+                        .parse(rest)?;
+                let item = Element::builder(&name).build();
+                open.last_mut().unwrap().children.push(Node::Element(item));
+                rest = next;
+            }
+            let (_, closed) = dedent(&stack).parse(rest).unwrap_or((rest, 0));
+            for _ in 0..closed {
+                let done = open.pop().unwrap();
+                open.last_mut().unwrap().children.push(Node::Element(done));
+            }
+            Ok(open.pop().unwrap())
+        }
+
+        let tree = outline("a\n  b\n    c\n      d\n  e\nf\n  g").unwrap();
+        assert_eq!(
+            tree.to_string(),
+            "<outline><a><b><c><d/></c></b><e/></a><f><g/></f></outline>"
+        );
+        assert_eq!(outline("a\n    b\n  c"), Err("  c"));
+
+        let stack = IndentStack::new();
+        assert_eq!(indent(&stack).parse("  x"), Ok(("  x", ())));
+        assert_eq!(indent(&stack).parse("    x"), Ok(("    x", ())));
+        assert_eq!(stack.current(), 4);
+        assert_eq!(dedent(&stack).parse("x"), Ok(("x", 2)));
+        assert_eq!(stack.current(), 0);
+        assert_eq!(dedent(&stack).parse("x"), Err("x"));
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = parse_str(r#"<a x="1"><b>hi</b></a>"#).unwrap();
+        let new = parse_str(r#"<a x="2" y="3"><b>hi</b><c/></a>"#).unwrap();
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Change::AttrChanged {
+                    path: String::from("/a"),
+                    name: String::from("x"),
+                    old: Some(String::from("1")),
+                    new: Some(String::from("2")),
+                },
+                Change::AttrChanged {
+                    path: String::from("/a"),
+                    name: String::from("y"),
+                    old: None,
+                    new: Some(String::from("3")),
+                },
+                Change::ChildAdded {
+                    path: String::from("/a/c[1]"),
+                    node: Node::Element(Element::builder("c").build()),
+                },
+            ]
+        );
+        assert_eq!(diff(&old, &old), vec![]);
+    }
+
+    #[test]
+    fn test_diff_text_and_removal() {
+        let old = parse_str("<a><b>hi</b><c/></a>").unwrap();
+        let new = parse_str("<a><b>bye</b></a>").unwrap();
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Change::TextChanged {
+                    path: String::from("/a/b[0]/#text[0]"),
+                    old: String::from("hi"),
+                    new: String::from("bye"),
+                },
+                Change::ChildRemoved {
+                    path: String::from("/a/c[1]"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_literals() {
+        let mut literals = Literals::default();
+        let lt = literals.get("<");
+        let gt = literals.get(">");
+        let tag = right(lt, left(tag_name(), gt));
+        let close = right(pair(lt, literals.get("/")), left(tag_name(), gt));
+        assert_eq!(tag.parse("<a>x"), Ok(("x", String::from("a"))));
+        assert_eq!(close.parse("</a>x"), Ok(("x", String::from("a"))));
+        assert_eq!(lt.parse("a"), Err("a"));
+
+        let again = literals.get(&"<<"[1..]);
+        assert_eq!(again, lt);
+        assert_eq!(again.0.as_ptr(), lt.0.as_ptr());
+        assert_eq!(literals.len(), 3);
+    }
+
+    #[test]
+    fn test_take_until_streaming() {
+        assert_eq!(take_until("-->").parse("abc"), Err("abc"));
+        assert_eq!(
+            take_until_streaming("-->")("abc"),
+            Err(StreamError::Incomplete)
+        );
+        assert_eq!(
+            take_until_streaming("-->")("abc--"),
+            Err(StreamError::Incomplete)
+        );
+        assert_eq!(take_until_streaming("-->")("abc-->d"), Ok(("-->d", "abc")));
+    }
+
+    #[test]
+    fn test_map2() {
+        let start = map2(
+            right(match_literal("<"), tag_name()),
+            attributes(),
+            |name, attributes| Element {
+                name,
+                attributes,
+                children: vec![],
+            },
+        );
+        assert_eq!(
+            start.parse(r#"<a x="1">"#),
+            Ok((">", Element::builder("a").attr("x", "1").build()))
+        );
+        assert_eq!(start.parse("a"), Err("a"));
+    }
+
+    #[test]
+    fn test_map3() {
+        let range = map3(
+            char_range('a', 'z'),
+            match_literal("-"),
+            char_range('a', 'z'),
+            |lo, _, hi| (lo, hi),
+        );
+        assert_eq!(range.parse("a-z]"), Ok(("]", ('a', 'z'))));
+        assert_eq!(range.parse("a-1"), Err("1"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parse_str_with_context() {
+        let error = parse_str_with_context("<a><b x=1/></a>").unwrap_err();
+        assert_eq!(error.offset, 8);
+        assert_eq!(error.context, vec!["attribute", "element", "element"]);
+        assert_eq!(
+            error.to_string(),
+            "while parsing attribute, while parsing element, while parsing element: \
+             parse error at byte 8: unexpected \"1/></a>\""
+        );
+
+        let error = parse_str_with_context("<a/>x").unwrap_err();
+        assert_eq!(error.context, Vec::<&str>::new());
+        assert_eq!(error.to_string(), "parse error at byte 4: unexpected \"x\"");
+
+        assert_eq!(
+            parse_str_with_context("<a><b/></a>"),
+            Ok(parse_str("<a><b/></a>").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_attr_value_lenient() {
+        assert_eq!(
+            attr_value_lenient().parse("text>"),
+            Ok((">", String::from("text")))
+        );
+        assert_eq!(
+            attr_value_lenient().parse("\"text\">"),
+            Ok((">", String::from("text")))
+        );
+        assert_eq!(attr_value_lenient().parse(">"), Err(">"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_html_config() {
+        let html = ParserConfig {
+            html: true,
+            ..ParserConfig::default()
+        };
+        let expected = Element::builder("input").attr("type", "text").build();
+        assert_eq!(
+            parse_str_with("<input type=text/>", &html),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            parse_str_with("<input type=\"text\"/>", &html),
+            Ok(expected)
+        );
+        assert_eq!(
+            parse_str_with("<a href=x/y></a>", &html),
+            Ok(Element::builder("a").attr("href", "x/y").build())
+        );
+        assert!(parse_str("<input type=text/>").is_err());
+        assert!(!html_mode());
+
+        let mut events = Vec::new();
+        assert_eq!(
+            parse_events_with("<input type=text/>", &html, |event| events.push(event)),
+            Ok(())
+        );
+        assert_eq!(
+            events[0],
+            Event::Open {
+                name: String::from("input"),
+                attributes: vec![(String::from("type"), String::from("text"))],
+            }
+        );
+        assert!(parse_events("<input type=text/>", |_| {}).is_err());
+        assert!(!html_mode());
+    }
+
+    #[test]
+    fn test_fold_many() {
+        let sum = fold_many(
+            char_range('0', '9'),
+            || 0,
+            |acc, c| acc + (c as u32 - '0' as u32),
+        );
+        assert_eq!(sum.parse("123x"), Ok(("x", 6)));
+        assert_eq!(sum.parse("x"), Ok(("x", 0)));
+    }
+
+    // Counts heap allocations made by the current thread, so tests running in
+    // parallel do not disturb each other.
+    #[cfg(feature = "std")]
+    mod counting_alloc {
+        use std::alloc::{GlobalAlloc, Layout, System};
+
+        struct Counting;
+
+        std::thread_local! {
+            static ALLOCATIONS: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+        }
+
+        unsafe impl GlobalAlloc for Counting {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+                System.alloc(layout)
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                System.dealloc(ptr, layout)
+            }
+        }
+
+        #[global_allocator]
+        static GLOBAL: Counting = Counting;
+
+        pub(super) fn allocations() -> usize {
+            ALLOCATIONS.with(core::cell::Cell::get)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_counting_parsers_do_not_allocate() {
+        let dots = ".".repeat(1000);
+        let count = many0_count(match_literal("."));
+        let fold = fold_many(any_char, || 0, |n, _| n + 1);
+
+        let before = counting_alloc::allocations();
+        let counted = count.parse(&dots);
+        let folded = fold.parse(&dots);
+        let after = counting_alloc::allocations();
+
+        assert_eq!(counted, Ok(("", 1000)));
+        assert_eq!(folded, Ok(("", 1000)));
+        assert_eq!(after - before, 0);
+
+        let before = counting_alloc::allocations();
+        let _ = zero_or_more(match_literal(".")).parse(&dots);
+        assert!(counting_alloc::allocations() > before);
+    }
+
+    #[test]
+    fn test_not_followed_by() {
+        assert_eq!(
+            not_followed_by(match_literal("x")).parse("ab"),
+            Ok(("ab", ()))
+        );
+        assert_eq!(not_followed_by(match_literal("x")).parse("xb"), Err("xb"));
+    }
+
+    #[test]
+    fn test_match_keyword() {
+        assert_eq!(match_keyword("if").parse("if x"), Ok((" x", "if")));
+        assert_eq!(match_keyword("if").parse("if(x)"), Ok(("(x)", "if")));
+        assert_eq!(match_keyword("if").parse("if"), Ok(("", "if")));
+        assert_eq!(match_keyword("if").parse("iffy"), Err("fy"));
+        assert_eq!(match_keyword("if").parse("else"), Err("else"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_to_map() {
+        let config = parse_str("<config><host>localhost</host><port>8080</port></config>").unwrap();
+        let map = config.to_map();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["host"], "localhost");
+        assert_eq!(map["port"], "8080");
+
+        let nested = parse_str("<config><empty/><db><host>x</host></db></config>").unwrap();
+        let map = nested.to_map();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["empty"], "");
+    }
+
+    #[test]
+    fn test_cursor() {
+        let doc = "<a>\n  <b/>\n</a>";
+        let cursor = Cursor::new(doc);
+        let (cursor, _) = cursor.parse(&open_element()).unwrap();
+        assert_eq!((cursor.offset, cursor.line, cursor.col), (3, 1, 4));
+        let (cursor, _) = cursor.parse(&space0()).unwrap();
+        assert_eq!((cursor.offset, cursor.line, cursor.col), (6, 2, 3));
+        assert_eq!(cursor.rest(), "<b/>\n</a>");
+        let (cursor, _) = cursor.parse(&right(single_element(), space0())).unwrap();
+        assert_eq!((cursor.line, cursor.col), (3, 1));
+        assert_eq!((cursor.line, cursor.col), locate(doc, cursor.rest()));
+
+        let failed =
+            Cursor::new("é\n<a x=1/>").parse(&right(pair(any_char, space0()), single_element()));
+        let failed = failed.unwrap_err();
+        assert_eq!((failed.line, failed.col), (2, 3));
+    }
+
+    #[test]
+    fn test_from_parts() {
+        #[derive(Debug, PartialEq)]
+        struct OpenTag {
+            name: String,
+            attributes: Vec<(String, String)>,
+        }
+
+        impl<'a> FromParts<(&'a str, String, Vec<(String, String)>, &'a str)> for OpenTag {
+            fn from_parts(
+                (_, name, attributes, _): (&'a str, String, Vec<(String, String)>, &'a str),
+            ) -> Self {
+                OpenTag { name, attributes }
+            }
+        }
+
+        let open_tag = from_parts::<_, _, OpenTag>((
+            match_literal("<"),
+            tag_name(),
+            attributes(),
+            match_literal(">"),
+        ));
+        assert_eq!(
+            open_tag.parse(r#"<a id="1">rest"#),
+            Ok((
+                "rest",
+                OpenTag {
+                    name: String::from("a"),
+                    attributes: vec![(String::from("id"), String::from("1"))],
+                }
+            ))
+        );
+        assert_eq!(open_tag.parse("<a/>"), Err("/>"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_grammar() {
+        let grammar = Grammar::new();
+        grammar.define(
+            "list",
+            map(
+                pair(
+                    grammar.rule("item"),
+                    zero_or_more(right(match_literal(","), grammar.rule("item"))),
+                ),
+                |(first, rest): (Vec<String>, Vec<Vec<String>>)| {
+                    first
+                        .into_iter()
+                        .chain(rest.into_iter().flatten())
+                        .collect()
+                },
+            ),
+        );
+        grammar.define(
+            "item",
+            either(
+                map(match_ident, |name| vec![name]),
+                right(
+                    match_literal("("),
+                    left(grammar.rule("list"), match_literal(")")),
+                ),
+            ),
+        );
+
+        let list = grammar.rule("list");
+        assert_eq!(
+            list.parse("a,b,c;"),
+            Ok((
+                ";",
+                vec![String::from("a"), String::from("b"), String::from("c")]
+            ))
+        );
+        assert_eq!(
+            list.parse("a,(b,(c)),d"),
+            Ok((
+                "",
+                vec![
+                    String::from("a"),
+                    String::from("b"),
+                    String::from("c"),
+                    String::from("d")
                 ]
             ))
-        )
+        );
+        assert_eq!(list.parse("(a,"), Err(","));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_grammar_undefined_rule() {
+        let grammar: Grammar<()> = Grammar::new();
+        let missing = grammar.rule("missing");
+        assert_eq!(missing.parse("x"), Err("x"));
+        assert_eq!(
+            grammar.validate(),
+            Err(String::from("undefined grammar rule: missing"))
+        );
+        grammar.define("missing", map(match_literal("x"), |_| ()));
+        assert_eq!(grammar.validate(), Ok(()));
+        assert_eq!(missing.parse("x"), Ok(("", ())));
+
+        let dangling = Grammar::<()>::new().rule("gone");
+        assert_eq!(dangling.parse("x"), Err("x"));
+    }
+
+    #[test]
+    fn test_satisfy() {
+        assert_eq!(satisfy(char::is_numeric).parse("7x"), Ok(("x", '7')));
+        assert_eq!(satisfy(char::is_numeric).parse("x7"), Err("x7"));
+        assert_eq!(satisfy(char::is_numeric).parse(""), Err(""));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_write_xml() {
+        let element = Element::builder("a")
+            .attr("x", "1 & \"2\"")
+            .child(Element::builder("b").text("t < u").build())
+            .child(Element::builder("c").build())
+            .text("tail")
+            .build();
+        let mut out = Vec::new();
+        write_xml(&element, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), to_xml(&element));
+        assert_eq!(
+            to_xml(&element),
+            r#"<a x="1 &amp; &quot;2&quot;"><b>t &lt; u</b><c/>tail</a>"#
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_lowercase_config() {
+        let doc = r#"<DIV CLASS="X"><Span/></DIV>"#;
+        let lowercase = ParserConfig {
+            lowercase_tags: true,
+            lowercase_attrs: true,
+            ..ParserConfig::default()
+        };
+        assert_eq!(
+            parse_str_with(doc, &lowercase),
+            Ok(Element::builder("div")
+                .attr("class", "X")
+                .child(Element::builder("span").build())
+                .build())
+        );
+
+        let tags_only = ParserConfig {
+            lowercase_tags: true,
+            ..ParserConfig::default()
+        };
+        let element = parse_str_with(doc, &tags_only).unwrap();
+        assert_eq!(element.name, "div");
+        assert_eq!(element.attributes[0].0, "CLASS");
+
+        assert_eq!(
+            parse_str_with(doc, &ParserConfig::default()),
+            parse_str(doc)
+        );
+        assert_eq!(parse_str(doc).unwrap().name, "DIV");
+
+        let strict = ParserConfig {
+            unique_attributes: true,
+            ..lowercase
+        };
+        assert!(parse_str_with(r#"<a ID="1" id="2"/>"#, &strict).is_err());
+
+        assert_eq!(
+            parse_str_with("<DIV><p></P></div>", &tags_only),
+            Ok(Element::builder("div")
+                .child(Element::builder("p").build())
+                .build())
+        );
+        assert!(parse_str("<DIV></div>").is_err());
+
+        let mut events = Vec::new();
+        assert_eq!(
+            parse_events_with(doc, &lowercase, |event| events.push(event)),
+            Ok(())
+        );
+        assert_eq!(
+            events[0],
+            Event::Open {
+                name: String::from("div"),
+                attributes: vec![(String::from("class"), String::from("X"))],
+            }
+        );
+        assert!(parse_events_with("<DIV></div>", &tags_only, |_| {}).is_ok());
+        assert!(parse_events("<DIV></div>", |_| {}).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parse_error_is_error() {
+        fn root_name(input: &str) -> Result<String, Box<dyn std::error::Error>> {
+            let element = parse_str_with_context(input)?;
+            Ok(element.name)
+        }
+
+        assert_eq!(root_name("<a/>").unwrap(), "a");
+        let error = root_name("<a/>x").unwrap_err();
+        assert!(error.downcast_ref::<ParseError>().is_some());
+        assert_eq!(error.to_string(), "parse error at byte 4: unexpected \"x\"");
+    }
+
+    #[test]
+    fn test_repeat_sep_fold() {
+        let digit = map(satisfy(|c| c.is_ascii_digit()), |c| c as u32 - '0' as u32);
+        let sum = repeat_sep_fold(digit, match_literal("+"), 0, |acc, n| acc + n);
+        assert_eq!(sum.parse("1+2+3"), Ok(("", 6)));
+        assert_eq!(sum.parse("1+2+"), Ok(("+", 3)));
+        assert_eq!(sum.parse("x"), Ok(("x", 0)));
+    }
+
+    #[test]
+    fn test_text_with_spans() {
+        let doc = "<p>Hello <b>bold</b> and\n <i>more</i>!</p>";
+        let spans = text_with_spans(doc).unwrap();
+        assert_eq!(
+            spans,
+            vec![
+                (String::from("Hello "), 3, 9),
+                (String::from("bold"), 12, 16),
+                (String::from(" and\n "), 20, 26),
+                (String::from("more"), 29, 33),
+                (String::from("!"), 37, 38),
+            ]
+        );
+        for (text, start, end) in &spans {
+            assert_eq!(&doc[*start..*end], text);
+        }
+        let element = parse_str(doc).unwrap();
+        let texts: String = spans.iter().map(|(text, _, _)| text.as_str()).collect();
+        assert_eq!(texts, element.text_content());
+
+        assert!(text_with_spans("<p>").is_err());
+    }
+
+    #[test]
+    fn test_ident_excluding() {
+        let ident = ident_excluding(&["let", "if"]);
+        assert_eq!(ident.parse("let x"), Err("let x"));
+        assert_eq!(ident.parse("if"), Err("if"));
+        assert_eq!(
+            ident.parse("lettuce x"),
+            Ok((" x", String::from("lettuce")))
+        );
+        assert_eq!(
+            either(map(match_keyword("let"), String::from), ident).parse("let"),
+            Ok(("", String::from("let")))
+        );
+    }
+
+    #[test]
+    fn test_count_matches() {
+        let doc = "<a><b>x < y</b><c/></a>";
+        assert_eq!(count_matches(match_literal("<"), doc), 6);
+        assert_eq!(count_matches(close_tag(), doc), 2);
+        assert_eq!(count_matches(match_literal("aa"), "aaaaa"), 2);
+        assert_eq!(count_matches(space0(), "a b"), 1);
+        assert_eq!(count_matches(match_literal("<"), ""), 0);
+    }
+
+    #[test]
+    fn test_sep_by() {
+        let digits = sep_by(char_range('0', '9'), match_literal(","));
+        assert_eq!(digits.parse("1,2,3"), Ok(("", vec!['1', '2', '3'])));
+        assert_eq!(digits.parse("1,2,"), Ok((",", vec!['1', '2'])));
+        assert_eq!(digits.parse("x"), Ok(("x", vec![])));
+    }
+
+    #[test]
+    fn test_collect_idents() {
+        assert_eq!(
+            collect_idents("alpha beta gamma-1 2bad"),
+            vec!["alpha", "beta", "gamma-1"]
+        );
+        assert_eq!(collect_idents("2bad"), Vec::<String>::new());
+        assert_eq!(collect_idents("one"), vec!["one"]);
+    }
+
+    #[cfg(feature = "trace-hooks")]
+    #[test]
+    fn test_trace_hooks() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let trace = |doc: &'static str| {
+            let events = Rc::new(RefCell::new(Vec::new()));
+            let sink = Rc::clone(&events);
+            let parsed = with_tracer(
+                move |event| sink.borrow_mut().push(event),
+                || parse_str(doc),
+            );
+            let events = events.borrow().clone();
+            (parsed, events)
+        };
+        // Every span must exit after everything entered inside it.
+        let assert_nested = |events: &[TraceEvent]| {
+            let mut open = Vec::new();
+            for event in events {
+                match event {
+                    TraceEvent::Enter(name) => open.push(name),
+                    TraceEvent::Exit(name) => assert_eq!(open.pop(), Some(name)),
+                }
+            }
+            assert!(open.is_empty());
+        };
+        let enter = |span: &str| TraceEvent::Enter(String::from(span));
+        let exit = |span: &str| TraceEvent::Exit(String::from(span));
+
+        let (parsed, events) = trace("<a><b><c/></b></a>");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            events,
+            vec![
+                enter("parse_str"),
+                enter("a"),
+                enter("b"),
+                enter("c"),
+                exit("c"),
+                enter("/b"),
+                exit("/b"),
+                exit("b"),
+                enter("/a"),
+                exit("/a"),
+                exit("a"),
+                exit("parse_str"),
+            ]
+        );
+        assert_nested(&events);
+
+        let (parsed, events) = trace("<a><b></a>");
+        assert!(parsed.is_err());
+        assert_nested(&events);
+        assert_eq!(events.first(), Some(&enter("parse_str")));
+        assert!(events.contains(&enter("/b")));
+
+        assert_eq!(
+            trace("<a/>").1,
+            vec![enter("parse_str"), enter("a"), exit("a"), exit("parse_str")]
+        );
     }
 }
@@ -0,0 +1,170 @@
+//! Closure-returning versions of the core combinators from `parser_fun`.
+//!
+//! `parser_fun` hands back `impl Parser`, which can only be named through the
+//! `Parser` trait. The constructors here return plain `impl Fn`, so a parser
+//! can be boxed as a `Box<dyn Fn>`, kept in a struct field and called later
+//! like any other function.
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, vec, vec::Vec};
+
+type ParseResult<'a, Output> = Result<(&'a str, Output), &'a str>;
+
+type BoxedFn<'a, Output> = Box<dyn Fn(&'a str) -> ParseResult<'a, Output> + 'a>;
+
+fn match_literal<'a>(expected: &'a str) -> impl Fn(&'a str) -> ParseResult<'a, &'a str> {
+    move |input| match input.get(0..expected.len()) {
+        Some(next) if next == expected => Ok((&input[expected.len()..], expected)),
+        _ => Err(input),
+    }
+}
+
+fn any_char<'a>() -> impl Fn(&'a str) -> ParseResult<'a, char> {
+    |input: &'a str| match input.chars().next() {
+        Some(next) => Ok((&input[next.len_utf8()..], next)),
+        _ => Err(input),
+    }
+}
+
+fn pred<'a, P, A, F>(parser: P, predicate: F) -> impl Fn(&'a str) -> ParseResult<'a, A>
+where
+    P: Fn(&'a str) -> ParseResult<'a, A>,
+    F: Fn(&A) -> bool,
+{
+    move |input| match parser(input) {
+        Ok((next_input, value)) if predicate(&value) => Ok((next_input, value)),
+        _ => Err(input),
+    }
+}
+
+fn pair<'a, P1, P2, R1, R2>(p1: P1, p2: P2) -> impl Fn(&'a str) -> ParseResult<'a, (R1, R2)>
+where
+    P1: Fn(&'a str) -> ParseResult<'a, R1>,
+    P2: Fn(&'a str) -> ParseResult<'a, R2>,
+{
+    move |input| {
+        p1(input).and_then(|(new_input, res1)| {
+            p2(new_input).map(|(rest_input, res2)| (rest_input, (res1, res2)))
+        })
+    }
+}
+
+fn map<'a, P, F, A, B>(parser: P, map_fn: F) -> impl Fn(&'a str) -> ParseResult<'a, B>
+where
+    P: Fn(&'a str) -> ParseResult<'a, A>,
+    F: Fn(A) -> B,
+{
+    move |input| parser(input).map(|(next, result)| (next, map_fn(result)))
+}
+
+fn left<'a, P1, P2, R1, R2>(p1: P1, p2: P2) -> impl Fn(&'a str) -> ParseResult<'a, R1>
+where
+    P1: Fn(&'a str) -> ParseResult<'a, R1>,
+    P2: Fn(&'a str) -> ParseResult<'a, R2>,
+{
+    map(pair(p1, p2), |(left, _right)| left)
+}
+
+fn right<'a, P1, P2, R1, R2>(p1: P1, p2: P2) -> impl Fn(&'a str) -> ParseResult<'a, R2>
+where
+    P1: Fn(&'a str) -> ParseResult<'a, R1>,
+    P2: Fn(&'a str) -> ParseResult<'a, R2>,
+{
+    map(pair(p1, p2), |(_left, right)| right)
+}
+
+fn either<'a, P1, P2, A>(p1: P1, p2: P2) -> impl Fn(&'a str) -> ParseResult<'a, A>
+where
+    P1: Fn(&'a str) -> ParseResult<'a, A>,
+    P2: Fn(&'a str) -> ParseResult<'a, A>,
+{
+    move |input| match p1(input) {
+        ok @ Ok(_) => ok,
+        Err(_) => p2(input),
+    }
+}
+
+fn zero_or_more<'a, P, A>(parser: P) -> impl Fn(&'a str) -> ParseResult<'a, Vec<A>>
+where
+    P: Fn(&'a str) -> ParseResult<'a, A>,
+{
+    move |input| {
+        let mut result = Vec::new();
+        let mut to_parse = input;
+        while let Ok((rest, parsed)) = parser(to_parse) {
+            result.push(parsed);
+            to_parse = rest;
+        }
+        Ok((to_parse, result))
+    }
+}
+
+fn one_or_more<'a, P, A>(parser: P) -> impl Fn(&'a str) -> ParseResult<'a, Vec<A>>
+where
+    P: Fn(&'a str) -> ParseResult<'a, A>,
+{
+    move |input| {
+        let (mut to_parse, first) = parser(input)?;
+        let mut result = vec![first];
+        while let Ok((rest, parsed)) = parser(to_parse) {
+            result.push(parsed);
+            to_parse = rest;
+        }
+        Ok((to_parse, result))
+    }
+}
+
+fn boxed<'a, P, Output>(parser: P) -> BoxedFn<'a, Output>
+where
+    P: Fn(&'a str) -> ParseResult<'a, Output> + 'a,
+{
+    Box::new(parser)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "std"))]
+    use alloc::string::String;
+
+    #[test]
+    fn test_pair() {
+        let parser = pair(match_literal("<"), left(any_char(), match_literal(">")));
+        assert_eq!(parser("<a>!"), Ok(("!", ("<", 'a'))));
+        assert_eq!(parser("<ab"), Err("b"));
+    }
+
+    #[test]
+    fn test_repetition() {
+        let digit = pred(any_char(), |c: &char| c.is_ascii_digit());
+        assert_eq!(one_or_more(&digit)("12x"), Ok(("x", vec!['1', '2'])));
+        assert_eq!(one_or_more(&digit)("x"), Err("x"));
+        assert_eq!(zero_or_more(&digit)("x"), Ok(("x", vec![])));
+    }
+
+    #[test]
+    fn test_stored_in_struct() {
+        struct Rule<'a> {
+            name: &'static str,
+            parser: BoxedFn<'a, String>,
+        }
+
+        let rule = Rule {
+            name: "word",
+            parser: boxed(map(
+                either(
+                    one_or_more(pred(any_char(), |c: &char| c.is_alphabetic())),
+                    right(match_literal("_"), zero_or_more(any_char())),
+                ),
+                |chars| chars.into_iter().collect(),
+            )),
+        };
+        assert_eq!(rule.name, "word");
+        assert_eq!(
+            (rule.parser)("hello world"),
+            Ok((" world", String::from("hello")))
+        );
+        assert_eq!((rule.parser)("_all"), Ok(("", String::from("all"))));
+        assert_eq!((rule.parser)("1"), Err("1"));
+    }
+}
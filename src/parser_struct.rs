@@ -1,3 +1,13 @@
+#[cfg(not(feature = "std"))]
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::parser_fun;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct Element {
     name: String,
@@ -9,6 +19,38 @@ type ParseResult<Output> = Result<(String, Output), String>;
 
 trait ParserStruct<Output> {
     fn parse(&self, input: String) -> ParseResult<Output>;
+
+    fn parse_str(&self, input: &str) -> ParseResult<Output> {
+        self.parse(input.to_string())
+    }
+}
+
+// For parsers with a single natural output, so it needs no type parameter
+// at the use site.
+trait Parse {
+    type Output;
+
+    fn parse(&self, input: &str) -> ParseResult<Self::Output>;
+}
+
+// Runs a `Parse` parser where a `ParserStruct` is expected.
+struct ParseAdapter<P>(P);
+
+impl<P: Parse> ParserStruct<P::Output> for ParseAdapter<P> {
+    fn parse(&self, input: String) -> ParseResult<P::Output> {
+        self.0.parse(&input)
+    }
+}
+
+// Runs a `ParserStruct` parser where a `Parse` is expected.
+struct ParserStructAdapter<'a, A>(&'a dyn ParserStruct<A>);
+
+impl<'a, A> Parse for ParserStructAdapter<'a, A> {
+    type Output = A;
+
+    fn parse(&self, input: &str) -> ParseResult<A> {
+        self.0.parse_str(input)
+    }
 }
 
 #[derive(Clone)]
@@ -41,7 +83,7 @@ impl ParserStruct<String> for IdentParser {
             _ => return Err(input),
         };
 
-        while let Some(next) = chars.next() {
+        for next in chars {
             if next.is_alphabetic() || next == '-' {
                 matched.push(next);
             } else {
@@ -87,9 +129,265 @@ impl<'a, A> ParserStruct<Vec<A>> for ZeroOrMoreParser<'a, A> {
     }
 }
 
+struct MapParser<'a, A, F> {
+    inner: &'a dyn ParserStruct<A>,
+    map_fn: F,
+}
+
+impl<'a, A, B, F> ParserStruct<B> for MapParser<'a, A, F>
+where
+    F: Fn(A) -> B,
+{
+    fn parse(&self, input: String) -> ParseResult<B> {
+        self.inner
+            .parse(input)
+            .map(|(rest, output)| (rest, (self.map_fn)(output)))
+    }
+}
+
+// Like `MapParser`, but the mapping may fail. Its message becomes the parse
+// error, the way `LabelParser` reports its label.
+struct TryMapParser<'a, A, F> {
+    inner: &'a dyn ParserStruct<A>,
+    map_fn: F,
+}
+
+impl<'a, A, B, F> ParserStruct<B> for TryMapParser<'a, A, F>
+where
+    F: Fn(A) -> Result<B, String>,
+{
+    fn parse(&self, input: String) -> ParseResult<B> {
+        let (rest, output) = self.inner.parse(input)?;
+        Ok((rest, (self.map_fn)(output)?))
+    }
+}
+
+// The longest prefix whose chars all satisfy `predicate`, possibly empty.
+struct TakeWhileParser<F> {
+    predicate: F,
+}
+
+impl<F> ParserStruct<String> for TakeWhileParser<F>
+where
+    F: Fn(char) -> bool,
+{
+    fn parse(&self, input: String) -> ParseResult<String> {
+        let end = input.find(|c| !(self.predicate)(c)).unwrap_or(input.len());
+        Ok((input[end..].to_string(), input[..end].to_string()))
+    }
+}
+
+// Runs every parser in turn and collects their outputs; all must succeed.
+struct SeqParser<A> {
+    parsers: Vec<Box<dyn ParserStruct<A>>>,
+}
+
+impl<A> ParserStruct<Vec<A>> for SeqParser<A> {
+    fn parse(&self, input: String) -> ParseResult<Vec<A>> {
+        let mut result = Vec::with_capacity(self.parsers.len());
+        let mut to_parse = input;
+        for parser in &self.parsers {
+            let (rest, parsed) = parser.parse(to_parse)?;
+            result.push(parsed);
+            to_parse = rest;
+        }
+        Ok((to_parse, result))
+    }
+}
+
+struct OptionalParser<'a, A> {
+    inner: &'a dyn ParserStruct<A>,
+}
+
+impl<'a, A> ParserStruct<Option<A>> for OptionalParser<'a, A> {
+    fn parse(&self, input: String) -> ParseResult<Option<A>> {
+        match self.inner.parse(input.clone()) {
+            Ok((rest, output)) => Ok((rest, Some(output))),
+            Err(_) => Ok((input, None)),
+        }
+    }
+}
+
+struct LabelParser<'a, A> {
+    inner: &'a dyn ParserStruct<A>,
+    label: String,
+}
+
+impl<'a, A> ParserStruct<A> for LabelParser<'a, A> {
+    fn parse(&self, input: String) -> ParseResult<A> {
+        self.inner.parse(input).map_err(|_| self.label.clone())
+    }
+}
+
+// Returns the text the inner parser consumed rather than its output.
+struct RecognizeParser<'a, A> {
+    inner: &'a dyn ParserStruct<A>,
+}
+
+impl<'a, A> ParserStruct<String> for RecognizeParser<'a, A> {
+    fn parse(&self, input: String) -> ParseResult<String> {
+        let (rest, _) = self.inner.parse(input.clone())?;
+        let consumed = input.len() - rest.len();
+        Ok((rest, input[..consumed].to_string()))
+    }
+}
+
+#[derive(Clone)]
+struct WhitespaceParser {}
+
+impl ParserStruct<()> for WhitespaceParser {
+    fn parse(&self, input: String) -> ParseResult<()> {
+        Ok((input.trim_start().to_string(), ()))
+    }
+}
+
+// Struct counterpart of `parser_fun::whitespace_wrap`: optional whitespace on
+// either side of the inner parser.
+struct WhitespaceWrapParser<'a, A> {
+    inner: &'a dyn ParserStruct<A>,
+}
+
+impl<'a, A> ParserStruct<A> for WhitespaceWrapParser<'a, A> {
+    fn parse(&self, input: String) -> ParseResult<A> {
+        let (rest, ()) = WhitespaceParser {}.parse(input)?;
+        let (rest, output) = self.inner.parse(rest)?;
+        let (rest, ()) = WhitespaceParser {}.parse(rest)?;
+        Ok((rest, output))
+    }
+}
+
+// ` name="value"` or ` name='value'`, with the leading whitespace required.
+struct AttributeParser {}
+
+impl ParserStruct<(String, String)> for AttributeParser {
+    fn parse(&self, input: String) -> ParseResult<(String, String)> {
+        if !input.starts_with(char::is_whitespace) {
+            return Err(input);
+        }
+        let equals = LiteralParser {
+            expected: String::from("="),
+        };
+        let start = PairParser {
+            parser_a: &IdentParser {},
+            parser_b: &equals,
+        };
+        let (rest, (name, _)) = start.parse(input.trim_start().to_string())?;
+        let quote = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => return Err(rest),
+        };
+        let value = &rest[1..];
+        match value.find(quote) {
+            Some(end) => Ok((
+                value[end + 1..].to_string(),
+                (name, value[..end].to_string()),
+            )),
+            None => Err(rest),
+        }
+    }
+}
+
+// The struct counterpart of `parser_fun::element`: a self-closing or nested
+// element, with whitespace allowed around it.
+struct ElementParser {}
+
+impl ParserStruct<Element> for ElementParser {
+    fn parse(&self, input: String) -> ParseResult<Element> {
+        let open = PairParser {
+            parser_a: &LiteralParser {
+                expected: String::from("<"),
+            },
+            parser_b: &IdentParser {},
+        };
+        let (rest, (_, name)) = open.parse(input.trim_start().to_string())?;
+        let (rest, attributes) = ZeroOrMoreParser {
+            parser: &AttributeParser {},
+        }
+        .parse(rest)?;
+        let mut element = Element {
+            name,
+            attributes,
+            children: Vec::new(),
+        };
+
+        let slash = OptionalParser {
+            inner: &LiteralParser {
+                expected: String::from("/"),
+            },
+        };
+        let end = PairParser {
+            parser_a: &slash,
+            parser_b: &LiteralParser {
+                expected: String::from(">"),
+            },
+        };
+        let rest = match end.parse(rest)? {
+            (rest, (Some(_), _)) => rest,
+            (rest, (None, _)) => {
+                let (rest, children) = ZeroOrMoreParser { parser: self }.parse(rest)?;
+                element.children = children;
+                let close = LiteralParser {
+                    expected: format!("</{}>", element.name),
+                };
+                close.parse(rest.trim_start().to_string())?.0
+            }
+        };
+        Ok((rest.trim_start().to_string(), element))
+    }
+}
+
+// Lets a `parser_fun` parser run inside the struct API. The wrapped parser
+// must work for any input lifetime and produce an owned output, since the
+// struct API hands out owned `String`s.
+struct FnAdapter<F>(F);
+
+impl<F> FnAdapter<F> {
+    fn new<A>(parser: F) -> Self
+    where
+        F: Fn(&str) -> parser_fun::ParseResult<'_, A>,
+    {
+        FnAdapter(parser)
+    }
+}
+
+impl<F, A> ParserStruct<A> for FnAdapter<F>
+where
+    F: Fn(&str) -> parser_fun::ParseResult<'_, A>,
+{
+    fn parse(&self, input: String) -> ParseResult<A> {
+        match (self.0)(&input) {
+            Ok((rest, output)) => Ok((rest.to_string(), output)),
+            Err(_) => Err(input),
+        }
+    }
+}
+
+// The other direction: lets a struct parser run wherever a `parser_fun`
+// parser is expected. The remaining input handed back by the struct parser is
+// a suffix of what it was given, so it maps back onto the borrowed input; a
+// rest that is not a suffix, such as a label's error, fails at the start.
+struct StructAdapter<P>(P);
+
+impl<'a, A, P> parser_fun::Parser<'a, A> for StructAdapter<P>
+where
+    P: ParserStruct<A>,
+{
+    fn parse(&self, input: &'a str) -> parser_fun::ParseResult<'a, A> {
+        let suffix = |rest: &str| &input[input.len() - rest.len()..];
+        match self.0.parse(input.to_string()) {
+            Ok((rest, output)) if input.ends_with(rest.as_str()) => Ok((suffix(&rest), output)),
+            Ok(_) => Err(input),
+            Err(rest) if input.ends_with(rest.as_str()) => Err(suffix(&rest)),
+            Err(_) => Err(input),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(not(feature = "std"))]
+    use alloc::vec;
 
     #[test]
     fn test_literal() {
@@ -157,4 +455,334 @@ mod tests {
             ))
         );
     }
+
+    #[test]
+    fn test_label() {
+        let less_parser = LiteralParser {
+            expected: String::from("<"),
+        };
+        let label_parser = LabelParser {
+            inner: &less_parser,
+            label: String::from("expected '<' to open a tag"),
+        };
+        assert_eq!(
+            label_parser.parse(String::from("<a")),
+            Ok((String::from("a"), String::from("<")))
+        );
+        assert_eq!(
+            label_parser.parse(String::from("a>")),
+            Err(String::from("expected '<' to open a tag"))
+        );
+    }
+
+    #[test]
+    fn test_fn_adapter() {
+        use crate::parser_fun::Parser;
+
+        let x_parser = FnAdapter::new(|input: &str| {
+            parser_fun::map(parser_fun::match_literal("x"), String::from).parse(input)
+        });
+        let ident_parser = FnAdapter::new(parser_fun::match_ident);
+        let pair_parser = PairParser {
+            parser_a: &x_parser,
+            parser_b: &ident_parser,
+        };
+        assert_eq!(
+            pair_parser.parse(String::from("xdemo-id>")),
+            Ok((
+                String::from(">"),
+                (String::from("x"), String::from("demo-id"))
+            ))
+        );
+        assert_eq!(
+            pair_parser.parse(String::from("ydemo")),
+            Err(String::from("ydemo"))
+        );
+    }
+
+    #[test]
+    fn test_struct_adapter() {
+        use crate::parser_fun::Parser;
+
+        let open = StructAdapter(LiteralParser {
+            expected: String::from("<"),
+        });
+        let open_tag = parser_fun::pair(open, StructAdapter(IdentParser {}));
+        assert_eq!(
+            open_tag.parse("<div>"),
+            Ok((">", (String::from("<"), String::from("div"))))
+        );
+        assert_eq!(open_tag.parse("<1>"), Err("1>"));
+
+        let label = LabelParser {
+            inner: &IdentParser {},
+            label: String::from("expected a name"),
+        };
+        assert_eq!(StructAdapter(label).parse("1"), Err("1"));
+
+        // A rest that is not a suffix of the input is rejected, not sliced.
+        struct Rewrite;
+        impl ParserStruct<()> for Rewrite {
+            fn parse(&self, _input: String) -> ParseResult<()> {
+                Ok((String::from("elsewhere"), ()))
+            }
+        }
+        assert_eq!(StructAdapter(Rewrite).parse("<a>"), Err("<a>"));
+    }
+
+    #[test]
+    fn test_parse_str() {
+        assert_eq!(
+            IdentParser {}.parse_str("demo-id>"),
+            Ok((String::from(">"), String::from("demo-id")))
+        );
+        assert_eq!(IdentParser {}.parse_str("1"), Err(String::from("1")));
+    }
+
+    #[test]
+    fn test_recognize() {
+        struct NumberParser;
+
+        impl ParserStruct<u32> for NumberParser {
+            fn parse(&self, input: String) -> ParseResult<u32> {
+                let end = input
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(input.len());
+                match input[..end].parse() {
+                    Ok(number) => Ok((input[end..].to_string(), number)),
+                    Err(_) => Err(input),
+                }
+            }
+        }
+
+        let recognize = RecognizeParser {
+            inner: &NumberParser,
+        };
+        assert_eq!(
+            recognize.parse(String::from("0042 rest")),
+            Ok((String::from(" rest"), String::from("0042")))
+        );
+        assert_eq!(recognize.parse(String::from("x")), Err(String::from("x")));
+    }
+
+    #[test]
+    fn test_whitespace_wrap() {
+        let parser = WhitespaceWrapParser {
+            inner: &LiteralParser {
+                expected: String::from("x"),
+            },
+        };
+        assert_eq!(
+            parser.parse(String::from("  x  y")),
+            Ok((String::from("y"), String::from("x")))
+        );
+        assert_eq!(
+            parser.parse(String::from("  x  ")),
+            Ok((String::new(), String::from("x")))
+        );
+        assert_eq!(
+            WhitespaceParser {}.parse(String::from("x")),
+            Ok((String::from("x"), ()))
+        );
+        assert!(parser.parse(String::from("  y")).is_err());
+    }
+
+    #[test]
+    fn test_parse_trait() {
+        struct DigitParser;
+
+        impl Parse for DigitParser {
+            type Output = u32;
+
+            fn parse(&self, input: &str) -> ParseResult<u32> {
+                match input.chars().next().and_then(|c| c.to_digit(10)) {
+                    Some(digit) => Ok((input[1..].to_string(), digit)),
+                    None => Err(input.to_string()),
+                }
+            }
+        }
+
+        assert_eq!(DigitParser.parse("7a"), Ok((String::from("a"), 7)));
+        assert_eq!(DigitParser.parse("a"), Err(String::from("a")));
+
+        let digit = ParseAdapter(DigitParser);
+        let pair_parser = PairParser {
+            parser_a: &digit,
+            parser_b: &IdentParser {},
+        };
+        assert_eq!(
+            pair_parser.parse(String::from("7abc")),
+            Ok((String::new(), (7, String::from("abc"))))
+        );
+
+        let ident = ParserStructAdapter(&IdentParser {});
+        assert_eq!(
+            ident.parse("abc1"),
+            Ok((String::from("1"), String::from("abc")))
+        );
+    }
+
+    #[test]
+    fn test_attribute() {
+        let pair = |name: &str, value: &str| (String::from(name), String::from(value));
+        assert_eq!(
+            AttributeParser {}.parse_str(" id=\"1\"/>"),
+            Ok((String::from("/>"), pair("id", "1")))
+        );
+        assert_eq!(
+            AttributeParser {}.parse_str(" title='say \"hi\"'>"),
+            Ok((String::from(">"), pair("title", "say \"hi\"")))
+        );
+        assert_eq!(
+            AttributeParser {}.parse_str(" id=\"1'>"),
+            Err(String::from("\"1'>"))
+        );
+        assert_eq!(
+            AttributeParser {}.parse_str(" id=1>"),
+            Err(String::from("1>"))
+        );
+    }
+
+    #[test]
+    fn test_element() {
+        assert_eq!(
+            ElementParser {}.parse_str("<a><b/></a>"),
+            Ok((
+                String::new(),
+                Element {
+                    name: String::from("a"),
+                    attributes: vec![],
+                    children: vec![Element {
+                        name: String::from("b"),
+                        attributes: vec![],
+                        children: vec![],
+                    }],
+                }
+            ))
+        );
+        assert_eq!(
+            ElementParser {}.parse_str("<top label=\"Top\">\n  <semi-bottom label=\"Bottom\"/>\n  <middle><bottom/></middle>\n</top>"),
+            Ok((
+                String::new(),
+                Element {
+                    name: String::from("top"),
+                    attributes: vec![(String::from("label"), String::from("Top"))],
+                    children: vec![
+                        Element {
+                            name: String::from("semi-bottom"),
+                            attributes: vec![(String::from("label"), String::from("Bottom"))],
+                            children: vec![],
+                        },
+                        Element {
+                            name: String::from("middle"),
+                            attributes: vec![],
+                            children: vec![Element {
+                                name: String::from("bottom"),
+                                attributes: vec![],
+                                children: vec![],
+                            }],
+                        },
+                    ],
+                }
+            ))
+        );
+        assert_eq!(
+            ElementParser {}.parse_str("<a><b></a>"),
+            Err(String::from("<b></a>"))
+        );
+    }
+
+    #[test]
+    fn test_optional() {
+        let slash = LiteralParser {
+            expected: String::from("/"),
+        };
+        let optional = OptionalParser { inner: &slash };
+        assert_eq!(
+            optional.parse(String::from("/>")),
+            Ok((String::from(">"), Some(String::from("/"))))
+        );
+        assert_eq!(
+            optional.parse(String::from(">")),
+            Ok((String::from(">"), None))
+        );
+    }
+
+    #[test]
+    fn test_take_while() {
+        let digits = TakeWhileParser {
+            predicate: |c: char| c.is_ascii_digit(),
+        };
+        assert_eq!(
+            digits.parse(String::from("123abc")),
+            Ok((String::from("abc"), String::from("123")))
+        );
+        assert_eq!(
+            digits.parse(String::from("abc")),
+            Ok((String::from("abc"), String::new()))
+        );
+        assert_eq!(
+            digits.parse(String::from("42")),
+            Ok((String::new(), String::from("42")))
+        );
+    }
+
+    #[test]
+    fn test_seq() {
+        let literal = |expected: &str| -> Box<dyn ParserStruct<String>> {
+            Box::new(LiteralParser {
+                expected: String::from(expected),
+            })
+        };
+        let seq = SeqParser {
+            parsers: vec![literal("a"), literal("b"), literal("c")],
+        };
+        assert_eq!(
+            seq.parse(String::from("abcd")),
+            Ok((
+                String::from("d"),
+                vec![String::from("a"), String::from("b"), String::from("c")]
+            ))
+        );
+        assert_eq!(seq.parse(String::from("abx")), Err(String::from("x")));
+    }
+
+    #[test]
+    fn test_map() {
+        let length = MapParser {
+            inner: &IdentParser {},
+            map_fn: |ident: String| ident.len(),
+        };
+        assert_eq!(
+            length.parse(String::from("demo-id>")),
+            Ok((String::from(">"), 7))
+        );
+    }
+
+    #[test]
+    fn test_try_map() {
+        let digits = TakeWhileParser {
+            predicate: |c: char| c.is_ascii_digit(),
+        };
+        let byte = TryMapParser {
+            inner: &digits,
+            map_fn: |digits: String| {
+                digits
+                    .parse::<u8>()
+                    .map_err(|_| format!("{:?} is not a byte value", digits))
+            },
+        };
+        assert_eq!(
+            byte.parse(String::from("255,")),
+            Ok((String::from(","), 255))
+        );
+        assert_eq!(
+            byte.parse(String::from("256,")),
+            Err(String::from("\"256\" is not a byte value"))
+        );
+        assert_eq!(
+            byte.parse(String::from("x")),
+            Err(String::from("\"\" is not a byte value"))
+        );
+    }
 }